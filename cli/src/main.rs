@@ -0,0 +1,153 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use gmod_tcp_client_core::{ClientConfig, MessageHandler, TcpClient};
+use gmod_tcp_shared::types::Message;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{info, warn, Level};
+
+/// Talks the game server side of the TCP protocol without launching GMod.
+/// Messages are printed to stdout as one JSON object per line, logs go to stderr.
+#[derive(Parser)]
+#[command(name = "gmod_tcp_cli", version)]
+struct Cli {
+    /// Directory with config.json and secret.txt, like the module's data/gmod_tcp.
+    #[arg(long, default_value = "data/gmod_tcp")]
+    data_dir: PathBuf,
+    /// Client uuid, overrides the one in config.json.
+    #[arg(long)]
+    uuid: Option<String>,
+    /// Server as host:port, overrides the list in config.json. May be repeated.
+    #[arg(long = "server")]
+    servers: Vec<String>,
+    /// Name reported on registration, overrides server_name in config.json.
+    #[arg(long)]
+    name: Option<String>,
+    /// Log at debug level instead of info.
+    #[arg(short, long)]
+    verbose: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Register the client, storing the issued secret in the data directory.
+    Register,
+    /// Fetch pending messages once.
+    Poll {
+        /// Acknowledge the printed messages so the server stops re-delivering them.
+        #[arg(long)]
+        ack: bool,
+    },
+    /// Acknowledge messages by id.
+    Ack {
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+    /// Keep a push session open and print messages as they arrive, until Ctrl-C.
+    Watch {
+        /// Acknowledge messages as soon as they are printed.
+        #[arg(long)]
+        ack: bool,
+    },
+}
+
+/// Prints every message it receives, acknowledging them when asked to.
+struct PrintHandler {
+    client: Arc<TcpClient>,
+    ack: bool,
+}
+
+impl MessageHandler for PrintHandler {
+    fn on_messages(&self, messages: Vec<Message>) {
+        print_messages(&messages);
+        if self.ack {
+            let client = Arc::clone(&self.client);
+            let ids = messages.iter().map(|message| message.id).collect();
+            tokio::spawn(async move {
+                if let Err(e) = client.ack(ids).await {
+                    warn!("Failed to acknowledge messages: {}", e);
+                }
+            });
+        }
+    }
+}
+
+fn print_messages(messages: &[Message]) {
+    for message in messages {
+        match serde_json::to_string(message) {
+            Ok(line) => println!("{}", line),
+            Err(e) => warn!("Failed to serialize message {}: {}", message.id, e),
+        }
+    }
+}
+
+/// Reads config.json when there is one, without creating it, and applies the command line overrides.
+fn load_config(cli: &Cli) -> Result<ClientConfig> {
+    let mut config = if ClientConfig::path(&cli.data_dir).exists() {
+        ClientConfig::load(&cli.data_dir)?
+    } else {
+        ClientConfig::default()
+    };
+    if let Some(uuid) = &cli.uuid {
+        config.uuid = Some(uuid.clone());
+    }
+    if !cli.servers.is_empty() {
+        config.servers = cli.servers.clone();
+    }
+    if let Some(name) = &cli.name {
+        config.server_name = Some(name.clone());
+    }
+    if config.uuid.is_none() {
+        return Err(anyhow::anyhow!(
+            "No uuid in {:?}, pass --uuid",
+            ClientConfig::path(&cli.data_dir)
+        ));
+    }
+    Ok(config)
+}
+
+async fn run(cli: &Cli, client: Arc<TcpClient>) -> Result<()> {
+    match &cli.command {
+        Command::Register => {
+            client.register().await?;
+            info!("Client {} registered, secret kept in {:?}", client.client_uuid, cli.data_dir);
+        }
+        Command::Poll { ack } => {
+            let messages = client.find_messages().await?;
+            print_messages(&messages);
+            if *ack {
+                client.ack(messages.iter().map(|message| message.id).collect()).await?;
+            }
+        }
+        Command::Ack { ids } => {
+            client.ack(ids.clone()).await?;
+            info!("Acknowledged {} message(s)", ids.len());
+        }
+        Command::Watch { ack } => {
+            client.hello().await?;
+            let handler = PrintHandler {
+                client: Arc::clone(&client),
+                ack: *ack,
+            };
+            client.listen(Arc::new(handler)).await?;
+            tokio::signal::ctrl_c().await?;
+            client.shutdown();
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(if cli.verbose { Level::DEBUG } else { Level::INFO })
+        .init();
+
+    let config = load_config(&cli)?;
+    let client = Arc::new(TcpClient::new(&config, &cli.data_dir)?);
+    run(&cli, client).await
+}
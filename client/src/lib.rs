@@ -1,32 +1,99 @@
-mod tcp;
+mod logging;
 
-use tcp::TcpClient;
+use gmod_tcp_client_core::{ClientConfig, MessageQueue, TcpClient};
 use std::sync::Arc;
 use std::sync::OnceLock;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
 
-use gmod::lua::State;
-use gmod_tcp_shared::types::Message;
+use gmod::lua::{LuaReference, State};
+use gmod_tcp_shared::types::{Message, ServerInfo};
 use std::ffi::CString;
+use std::path::Path;
+use tracing::{debug, info, warn, error};
 
 #[macro_use] extern crate gmod;
 
-static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
-static MESSAGE_QUEUE: OnceLock<Mutex<Vec<Message>>> = OnceLock::new();
-static CLIENT: OnceLock<Arc<TcpClient>> = OnceLock::new();
+/// Everything the module keeps on disk lives here, relative to the GMod root.
+const DATA_DIR: &str = "data/gmod_tcp";
+/// How long `gmod13_close` waits for in-flight requests before abandoning them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
-fn get_runtime() -> &'static tokio::runtime::Runtime {
-    RUNTIME.get_or_init(|| {
-        tokio::runtime::Runtime::new().expect("Failed to create tokio runtime")
-    })
+// The runtime and client live between gmod13_open and gmod13_close, so the module can be opened again in the same process.
+static RUNTIME: Mutex<Option<tokio::runtime::Runtime>> = Mutex::new(None);
+static CLIENT: RwLock<Option<Arc<TcpClient>>> = RwLock::new(None);
+static MESSAGE_QUEUE: OnceLock<Arc<MessageQueue>> = OnceLock::new();
+/// Lua functions registered with `GModTCPOn`, by message type, held as registry references.
+static CALLBACKS: OnceLock<Mutex<HashMap<String, Vec<LuaReference>>>> = OnceLock::new();
+/// When set, `message_data` is handed to Lua as a JSON string like older versions of the module did.
+static JSON_STRINGS: AtomicBool = AtomicBool::new(false);
+/// Lua function registered with `GModTCPOnLog`, called with WARN/ERROR lines.
+static LOG_CALLBACK: Mutex<Option<LuaReference>> = Mutex::new(None);
+
+
+fn get_message_queue() -> &'static Arc<MessageQueue> {
+    MESSAGE_QUEUE.get_or_init(|| Arc::new(MessageQueue::open(&Path::new(DATA_DIR).join("journal.json"))))
+}
+
+/// Runs `task` with the client on the module runtime. Returns false when the module is not running.
+fn spawn_with_client<F, Fut>(context: &str, task: F) -> bool
+where
+    F: FnOnce(Arc<TcpClient>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let Some(handle) = RUNTIME.lock().unwrap().as_ref().map(|rt| rt.handle().clone()) else {
+        warn!("{}: Module is not running", context);
+        return false;
+    };
+    let Some(client) = CLIENT.read().unwrap().clone() else {
+        warn!("{}: Client is not initialized", context);
+        return false;
+    };
+    handle.spawn(task(client));
+    true
 }
 
-fn get_message_queue() -> &'static Mutex<Vec<Message>> {
-    MESSAGE_QUEUE.get_or_init(|| Mutex::new(Vec::new()))
+fn get_callbacks() -> &'static Mutex<HashMap<String, Vec<LuaReference>>> {
+    CALLBACKS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-fn get_client() -> &'static Arc<TcpClient> {
-    CLIENT.get().expect("Client not initialized")
+/// Tells the server the messages reached Lua so it stops re-delivering them.
+fn ack_in_background(ids: Vec<u64>, context: &'static str) {
+    spawn_with_client(context, |client| async move {
+        if let Err(e) = client.ack(ids).await {
+            warn!("{}: Failed to acknowledge messages: {}", context, e);
+        }
+    });
+}
+
+/// Pushes a JSON value as the equivalent Lua value: objects and arrays become tables, null becomes nil.
+fn push_json_value(lua: State, value: &serde_json::Value) {
+    unsafe {
+        match value {
+            serde_json::Value::Null => lua.push_nil(),
+            serde_json::Value::Bool(boolean) => lua.push_boolean(*boolean),
+            serde_json::Value::Number(number) => lua.push_number(number.as_f64().unwrap_or_default()),
+            serde_json::Value::String(string) => lua.push_string(string),
+            serde_json::Value::Array(array) => {
+                lua.create_table(array.len() as i32, 0);
+                for (i, item) in array.iter().enumerate() {
+                    push_json_value(lua, item);
+                    lua.raw_seti(-2, (i + 1) as i32);
+                }
+            }
+            serde_json::Value::Object(object) => {
+                lua.create_table(0, object.len() as i32);
+                for (key, item) in object {
+                    lua.push_string(key);
+                    push_json_value(lua, item);
+                    lua.set_table(-3);
+                }
+            }
+        }
+    }
 }
 
 fn push_messages_to_lua(lua: State, messages: &[Message]) {
@@ -34,47 +101,67 @@ fn push_messages_to_lua(lua: State, messages: &[Message]) {
         lua.new_table();
         for (i, message) in messages.iter().enumerate() {
             lua.push_integer((i + 1) as isize);
-            lua.new_table();
-            
-            lua.push_string("id");
-            lua.push_integer(message.id as isize);
-            lua.set_table(-3);
-            
-            lua.push_string("client_uuid");
-            lua.push_string(&message.client_uuid);
+            push_message_to_lua(lua, message);
             lua.set_table(-3);
-            
-            lua.push_string("message_type");
-            lua.push_string(&message.message_type);
+        }
+    }
+}
+
+fn push_message_to_lua(lua: State, message: &Message) {
+    unsafe {
+        lua.new_table();
+        
+        lua.push_string("id");
+        lua.push_integer(message.id as isize);
+        lua.set_table(-3);
+        
+        lua.push_string("client_uuid");
+        lua.push_string(&message.client_uuid);
+        lua.set_table(-3);
+        
+        lua.push_string("message_type");
+        lua.push_string(&message.message_type);
+        lua.set_table(-3);
+        
+        lua.push_string("status");
+        lua.push_string(&message.status);
+        lua.set_table(-3);
+        
+        lua.push_string("created_at");
+        lua.push_string(&message.created_at.to_rfc3339());
+        lua.set_table(-3);
+        
+        if let Some(delivered_at) = message.delivered_at {
+            lua.push_string("delivered_at");
+            lua.push_string(&delivered_at.to_rfc3339());
             lua.set_table(-3);
-            
-            lua.push_string("status");
-            lua.push_string(&message.status);
+        }
+        
+        if let Some(outcome) = &message.outcome {
+            lua.push_string("outcome");
+            lua.push_string(outcome);
             lua.set_table(-3);
-            
-            lua.push_string("created_at");
-            lua.push_string(&message.created_at.to_rfc3339());
+        }
+        
+        if let Some(failure_reason) = &message.failure_reason {
+            lua.push_string("failure_reason");
+            lua.push_string(failure_reason);
             lua.set_table(-3);
-            
-            if let Some(delivered_at) = message.delivered_at {
-                lua.push_string("delivered_at");
-                lua.push_string(&delivered_at.to_rfc3339());
-                lua.set_table(-3);
-            }
-            
-            lua.push_string("message_data");
+        }
+        
+        lua.push_string("message_data");
+        if JSON_STRINGS.load(Ordering::Relaxed) {
             let data_str = serde_json::to_string(&message.message_data).unwrap_or_else(|_| "{}".to_string());
             lua.push_string(&data_str);
-            lua.set_table(-3);
-            
-            lua.set_table(-3);
+        } else {
+            push_json_value(lua, &message.message_data);
         }
+        lua.set_table(-3);
     }
 }
 
 unsafe extern "C-unwind" fn get_messages(lua: State) -> i32 {
-    let queue = get_message_queue();
-    let mut messages = queue.lock().unwrap();
+    let messages = get_message_queue().take_all();
 
     if messages.is_empty() {
         unsafe {
@@ -85,41 +172,286 @@ unsafe extern "C-unwind" fn get_messages(lua: State) -> i32 {
     
     push_messages_to_lua(lua, &messages);
     
-    messages.clear();
+    let ids: Vec<u64> = messages.iter().map(|message| message.id).collect();
+    ack_in_background(ids, "GetMessages");
     1
 }
 
+unsafe extern "C-unwind" fn on_message(lua: State) -> i32 {
+    let message_type = unsafe { lua.check_string(1) }.into_owned();
+    unsafe {
+        lua.check_function(2);
+        lua.push_value(2);
+    }
+    let callback = unsafe { lua.reference() };
+    get_callbacks().lock().unwrap().entry(message_type).or_default().push(callback);
+    
+    unsafe {
+        lua.push_boolean(true);
+    }
+    1
+}
+
+/// Registers `fn(level, line)` for WARN/ERROR log lines, e.g. to notify admins in chat. `nil` unregisters it.
+unsafe extern "C-unwind" fn on_log(lua: State) -> i32 {
+    let callback = unsafe {
+        if lua.is_nil(1) {
+            None
+        } else {
+            lua.check_function(1);
+            lua.push_value(1);
+            Some(lua.reference())
+        }
+    };
+    logging::forward_to_lua(callback.is_some());
+    if let Some(previous) = std::mem::replace(&mut *LOG_CALLBACK.lock().unwrap(), callback) {
+        unsafe {
+            lua.dereference(previous);
+        }
+    }
+    0
+}
+
+/// Hands log lines queued since the last tick to the `GModTCPOnLog` callback.
+fn dispatch_log_lines(lua: State) {
+    let Some(callback) = *LOG_CALLBACK.lock().unwrap() else {
+        return;
+    };
+    for (level, line) in logging::take_forwarded() {
+        unsafe {
+            lua.from_reference(callback);
+            lua.push_string(&level.as_str().to_lowercase());
+            lua.push_string(&line);
+            // Not logged on failure, that would feed the error straight back into the callback.
+            lua.pcall_ignore(2, 0);
+        }
+    }
+}
+
+/// Think hook: hands queued messages that have callbacks registered to them, on the main thread.
+unsafe extern "C-unwind" fn dispatch_messages(lua: State) -> i32 {
+    dispatch_log_lines(lua);
+    // Locks are released before calling into Lua, callbacks may call back into the module.
+    let (messages, callbacks) = {
+        let callbacks = get_callbacks().lock().unwrap();
+        if callbacks.is_empty() {
+            return 0;
+        }
+        let dispatched = get_message_queue().take_matching(|message| callbacks.contains_key(&message.message_type));
+        (dispatched, callbacks.clone())
+    };
+    if messages.is_empty() {
+        return 0;
+    }
+    
+    for message in &messages {
+        for callback in &callbacks[&message.message_type] {
+            unsafe {
+                lua.from_reference(*callback);
+                push_message_to_lua(lua, message);
+                // A throwing callback is reported to the console and does not stop the others.
+                if !lua.pcall_ignore(1, 0) {
+                    warn!("Dispatch: {} callback failed for message {}", message.message_type, message.id);
+                }
+            }
+        }
+    }
+    
+    ack_in_background(messages.iter().map(|message| message.id).collect(), "Dispatch");
+    0
+}
+
 unsafe extern "C-unwind" fn poll_now(lua: State) -> i32 {
-    let rt = get_runtime();
-    let client = Arc::clone(get_client());
     let queue = get_message_queue();
     
-    rt.spawn(async move {
+    let started = spawn_with_client("PollNow", |client| async move {
         match client.find_messages().await {
             Ok(messages) => {
                 if !messages.is_empty() {
-                    let mut queue_guard = queue.lock().unwrap();
-                    queue_guard.extend(messages);
-                    println!("PollNow: Added {} message(s) to queue", queue_guard.len());
+                    let added = queue.push(messages);
+                    info!("PollNow: Added {} message(s) to queue, {} waiting", added, queue.len());
                 } else {
-                    println!("PollNow: No new messages");
+                    debug!("PollNow: No new messages");
                 }
             }
             Err(e) => {
-                eprintln!("PollNow: Failed to poll messages: {}", e);
+                warn!("PollNow: Failed to poll messages: {}", e);
             }
         }
     });
     
     unsafe {
-        lua.push_boolean(true);
+        lua.push_boolean(started);
+    }
+    1
+}
+
+unsafe extern "C-unwind" fn ack_message(lua: State) -> i32 {
+    let id = unsafe { lua.check_integer(1) } as u64;
+    
+    let started = spawn_with_client("Ack", |client| async move {
+        if let Err(e) = client.report_applied(id).await {
+            warn!("Ack: Failed to report message {} as applied: {}", id, e);
+        }
+    });
+    
+    unsafe {
+        lua.push_boolean(started);
+    }
+    1
+}
+
+unsafe extern "C-unwind" fn nack_message(lua: State) -> i32 {
+    let id = unsafe { lua.check_integer(1) } as u64;
+    let reason = unsafe { lua.get_string(2) }
+        .map(|reason| reason.into_owned())
+        .unwrap_or_else(|| "unknown".to_string());
+    // The server re-delivers a nacked message, it must not be dropped as a duplicate.
+    get_message_queue().forget(id);
+    let started = spawn_with_client("Nack", |client| async move {
+        if let Err(e) = client.report_failed(id, reason).await {
+            warn!("Nack: Failed to report message {} as failed: {}", id, e);
+        }
+    });
+    
+    unsafe {
+        lua.push_boolean(started);
+    }
+    1
+}
+
+unsafe extern "C-unwind" fn use_json_strings(lua: State) -> i32 {
+    let enabled = unsafe { lua.check_boolean(1) };
+    JSON_STRINGS.store(enabled, Ordering::Relaxed);
+    0
+}
+
+unsafe extern "C-unwind" fn active_server(lua: State) -> i32 {
+    let client = CLIENT.read().unwrap().clone();
+    match client.as_ref().and_then(|client| client.active_endpoint()) {
+        Some(endpoint) => unsafe {
+            lua.push_string(&endpoint.to_string());
+        },
+        None => unsafe {
+            lua.push_nil();
+        },
+    }
+    1
+}
+
+/// Diagnostics for admins: a table with registration state, last poll, queue depth,
+/// reconnect count, server version and the last error. Only `running` is set while the module is stopped.
+unsafe extern "C-unwind" fn status(lua: State) -> i32 {
+    let client = CLIENT.read().unwrap().clone();
+    unsafe {
+        lua.new_table();
+
+        lua.push_string("running");
+        lua.push_boolean(client.is_some());
+        lua.set_table(-3);
+
+        lua.push_string("queued");
+        lua.push_integer(get_message_queue().len() as isize);
+        lua.set_table(-3);
+
+        let Some(client) = client else {
+            return 1;
+        };
+        let status = client.status();
+
+        lua.push_string("uuid");
+        lua.push_string(&client.client_uuid);
+        lua.set_table(-3);
+
+        lua.push_string("registered");
+        lua.push_boolean(status.registered);
+        lua.set_table(-3);
+
+        lua.push_string("session_active");
+        lua.push_boolean(status.session_active);
+        lua.set_table(-3);
+
+        lua.push_string("reconnects");
+        lua.push_integer(status.reconnects as isize);
+        lua.set_table(-3);
+
+        if let Some(endpoint) = client.active_endpoint() {
+            lua.push_string("active_server");
+            lua.push_string(&endpoint.to_string());
+            lua.set_table(-3);
+        }
+
+        if let Some(last_poll_time) = status.last_poll_time {
+            lua.push_string("last_poll_time");
+            lua.push_integer(last_poll_time as isize);
+            lua.set_table(-3);
+
+            lua.push_string("last_poll_ok");
+            lua.push_boolean(status.last_poll_ok.unwrap_or(false));
+            lua.set_table(-3);
+
+            lua.push_string("last_poll_messages");
+            lua.push_integer(status.last_poll_messages as isize);
+            lua.set_table(-3);
+        }
+
+        if let Some(server_version) = &status.server_version {
+            lua.push_string("server_version");
+            lua.push_string(server_version);
+            lua.set_table(-3);
+        }
+
+        if let Some(protocol_version) = status.protocol_version {
+            lua.push_string("protocol_version");
+            lua.push_integer(protocol_version as isize);
+            lua.set_table(-3);
+        }
+
+        if let Some(last_error) = &status.last_error {
+            lua.push_string("last_error");
+            lua.push_string(last_error);
+            lua.set_table(-3);
+        }
+
+        if let Some(last_error_time) = status.last_error_time {
+            lua.push_string("last_error_time");
+            lua.push_integer(last_error_time as isize);
+            lua.set_table(-3);
+        }
     }
     1
 }
 
+/// Re-reads the config and restarts the client with it. Returns `true`, or `false` and the validation error.
+unsafe extern "C-unwind" fn reload_config(lua: State) -> i32 {
+    let handle = RUNTIME.lock().unwrap().as_ref().map(|rt| rt.handle().clone());
+    let result = match handle {
+        Some(handle) => ClientConfig::load(Path::new(DATA_DIR)).and_then(|config| start_client(&handle, &config, read_server_info(lua, &config))),
+        None => Err(anyhow::anyhow!("Module is not running")),
+    };
+    match result {
+        Ok(()) => {
+            info!("ReloadConfig: Client restarted with {:?}", ClientConfig::path(Path::new(DATA_DIR)));
+            unsafe {
+                lua.push_boolean(true);
+            }
+            1
+        }
+        Err(e) => {
+            error!("ReloadConfig: {}", e);
+            unsafe {
+                lua.push_boolean(false);
+                lua.push_string(&e.to_string());
+            }
+            2
+        }
+    }
+}
+
 #[gmod13_open]
 fn gmod13_open(state: State) -> i32 {
-    println!("Module start loading!");
+    logging::init();
+    info!("Module start loading!");
 
     unsafe {
         state.new_table();
@@ -128,52 +460,172 @@ fn gmod13_open(state: State) -> i32 {
         state.set_global(CString::new("GModTCPGetMessages").unwrap().as_ptr());
         state.push_function(poll_now);
         state.set_global(CString::new("GModTCPPollNow").unwrap().as_ptr());
+        state.push_function(ack_message);
+        state.set_global(CString::new("GModTCPAck").unwrap().as_ptr());
+        state.push_function(nack_message);
+        state.set_global(CString::new("GModTCPNack").unwrap().as_ptr());
+        state.push_function(use_json_strings);
+        state.set_global(CString::new("GModTCPUseJSONStrings").unwrap().as_ptr());
+        state.push_function(on_message);
+        state.set_global(CString::new("GModTCPOn").unwrap().as_ptr());
+        state.push_function(reload_config);
+        state.set_global(CString::new("GModTCPReloadConfig").unwrap().as_ptr());
+        state.push_function(active_server);
+        state.set_global(CString::new("GModTCPActiveServer").unwrap().as_ptr());
+        state.push_function(status);
+        state.set_global(CString::new("GModTCPStatus").unwrap().as_ptr());
+        state.push_function(on_log);
+        state.set_global(CString::new("GModTCPOnLog").unwrap().as_ptr());
+
+        state.get_global(CString::new("hook").unwrap().as_ptr());
+        state.get_field(-1, CString::new("Add").unwrap().as_ptr());
+        state.push_string("Think");
+        state.push_string("GModTCPDispatch");
+        state.push_function(dispatch_messages);
+        state.call(3, 0);
+        state.pop();
     }
     
-    let rt = get_runtime();
-    
-    let client = match rt.block_on(async {
-        TcpClient::new().await
-    }) {
-        Ok(client) => Arc::new(client),
+    // A previous open that was never closed would otherwise keep its listen loop running.
+    shutdown();
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
         Err(e) => {
-            eprintln!("Failed to create TCP client: {}", e);
+            error!("Failed to create tokio runtime: {}", e);
             return 1;
         }
     };
     
-    println!("Client UUID: {}", client.client_uuid);
-    println!("Registering client on server");
+    let message_queue = get_message_queue();
+    if !message_queue.is_empty() {
+        info!("{} message(s) restored from the journal", message_queue.len());
+    }
+    
+    // The runtime stays up with a broken config so GModTCPReloadConfig can start the client later.
+    let started = ClientConfig::load(Path::new(DATA_DIR)).and_then(|config| start_client(rt.handle(), &config, read_server_info(state, &config)));
+    *RUNTIME.lock().unwrap() = Some(rt);
+    if let Err(e) = started {
+        error!("Failed to create TCP client: {}", e);
+        unsafe {
+            error_no_halt(state, &format!("GModTCP: {}\n", e));
+        }
+        return 1;
+    }
     
-    CLIENT.set(Arc::clone(&client)).expect("Failed to set client");
+    info!("Module loaded successfully!");
+    0
+}
+
+/// Reports an error to the server console without interrupting the calling Lua code.
+unsafe fn error_no_halt(lua: State, message: &str) {
+    unsafe {
+        lua.get_global(CString::new("ErrorNoHalt").unwrap().as_ptr());
+        lua.push_string(message);
+        lua.call(1, 0);
+    }
+}
+
+/// Calls a Lua function that takes no arguments and returns a string, like `game.GetMap`.
+unsafe fn call_for_string(lua: State, table: Option<&str>, function: &str) -> Option<String> {
+    unsafe {
+        let top = lua.get_top();
+        match table {
+            Some(table) => {
+                lua.get_global(CString::new(table).unwrap().as_ptr());
+                lua.get_field(-1, CString::new(function).unwrap().as_ptr());
+            }
+            None => lua.get_global(CString::new(function).unwrap().as_ptr()),
+        }
+        let value = if lua.pcall_ignore(0, 1) {
+            lua.get_string(-1).map(|value| value.into_owned()).filter(|value| !value.is_empty())
+        } else {
+            None
+        };
+        lua.set_top(top);
+        value
+    }
+}
+
+/// What the server reports on registration: the configured name, the current map and the hostname convar.
+fn read_server_info(lua: State, config: &ClientConfig) -> ServerInfo {
+    unsafe {
+        ServerInfo {
+            server_name: config.server_name.clone(),
+            map: call_for_string(lua, Some("game"), "GetMap"),
+            hostname: call_for_string(lua, None, "GetHostName"),
+        }
+    }
+}
+
+/// Creates a client from `config` and starts registration and the listen loop, replacing any running client.
+fn start_client(handle: &tokio::runtime::Handle, config: &ClientConfig, server_info: ServerInfo) -> anyhow::Result<()> {
+    let client = Arc::new(TcpClient::new(config, Path::new(DATA_DIR))?);
+    client.set_server_info(server_info);
+    logging::set_level(config.log_level);
     
-    let client_for_reg = Arc::clone(&client);
-    rt.spawn(async move {
-        match client_for_reg.register().await {
+    info!("Client UUID: {}", client.client_uuid);
+    if let Some(server_name) = &config.server_name {
+        info!("Server name: {}", server_name);
+    }
+    info!("Log level: {:?}", config.log_level);
+    info!("Registering client on server");
+    
+    if let Some(previous) = CLIENT.write().unwrap().replace(Arc::clone(&client)) {
+        previous.shutdown();
+    }
+    
+    let message_queue = Arc::clone(get_message_queue());
+    let client_for_listen = Arc::clone(&client);
+    handle.spawn(async move {
+        // Registration issues the secret every later request is signed with, so it has to finish first.
+        match client_for_listen.register().await {
             Ok(_) => {
-                println!("Client registered successfully");
+                info!("Client registered successfully");
             }
             Err(e) => {
-                eprintln!("Failed to register client: {}", e);
+                error!("Failed to register client, the listen loop retries: {}", e);
             }
         }
-    });
-    
-    println!("Starting polling loop (every 10 minutes)");
-    
-    let message_queue = get_message_queue();
-    let client_for_listen = Arc::clone(&client);
-    rt.spawn(async move {
+        info!("Starting push session (falls back to polling while disconnected)");
         if let Err(e) = client_for_listen.listen(message_queue).await {
-            eprintln!("Failed to start listening: {}", e);
+            error!("Failed to start listening: {}", e);
         }
     });
-    
-    println!("Module loaded successfully!");
-    0
+    Ok(())
+}
+
+/// Stops the listen loop and shuts the runtime down, abandoning requests that outlive `SHUTDOWN_TIMEOUT`.
+fn shutdown() {
+    if let Some(client) = CLIENT.write().unwrap().take() {
+        client.shutdown();
+    }
+    if let Some(rt) = RUNTIME.lock().unwrap().take() {
+        rt.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        info!("Runtime stopped");
+    }
 }
 
 #[gmod13_close]
-fn gmod13_close(_state: State) -> i32 {
+fn gmod13_close(state: State) -> i32 {
+    info!("Module unloading");
+    // The registry references belong to the closing Lua state and mean nothing in the next one.
+    for callback in get_callbacks().lock().unwrap().drain().flat_map(|(_, callbacks)| callbacks) {
+        unsafe {
+            state.dereference(callback);
+        }
+    }
+    if let Some(callback) = LOG_CALLBACK.lock().unwrap().take() {
+        logging::forward_to_lua(false);
+        unsafe {
+            state.dereference(callback);
+        }
+    }
+    shutdown();
+    // Undelivered messages are already journaled and are replayed by the next gmod13_open.
+    let pending = get_message_queue().len();
+    if pending > 0 {
+        info!("{} message(s) left in the journal", pending);
+    }
+    info!("Module unloaded");
     0
 }
\ No newline at end of file
@@ -0,0 +1,217 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{LazyLock, Mutex, Once};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use gmod_tcp_client_core::LogLevel;
+
+use crate::DATA_DIR;
+
+/// How many WARN/ERROR lines wait for the Lua callback before the oldest are dropped.
+const MAX_FORWARDED: usize = 100;
+/// Daily log files kept under `data/gmod_tcp/logs`.
+const LOG_FILES_KEPT: usize = 7;
+
+static INIT: Once = Once::new();
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+static FORWARD_TO_LUA: AtomicBool = AtomicBool::new(false);
+static FORWARDED: Mutex<VecDeque<(Level, String)>> = Mutex::new(VecDeque::new());
+
+/// `STEAM_0:1:1234`, `[U:1:2468]` and 64-bit `76561197960267966` forms.
+static STEAM_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"STEAM_[0-5]:[01]:\d+|\[U:1:\d+\]|\b7656119\d{10}\b").unwrap()
+});
+
+fn current_level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warn,
+        2 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+/// Module and client library events pass at the configured level, other dependencies only from WARN up.
+fn enabled(metadata: &Metadata<'_>) -> bool {
+    let max_level = if metadata.target().starts_with("gmod_tcp_client") {
+        Level::from(current_level())
+    } else {
+        Level::WARN
+    };
+    *metadata.level() <= max_level
+}
+
+/// SteamIDs are only written in full at `debug`, otherwise all but the last 4 digits are masked.
+fn redact(text: &str) -> Cow<'_, str> {
+    if current_level() >= LogLevel::Debug {
+        return Cow::Borrowed(text);
+    }
+    mask_steam_ids(text)
+}
+
+fn mask_steam_ids(text: &str) -> Cow<'_, str> {
+    STEAM_ID.replace_all(text, |captures: &regex::Captures| {
+        let account = captures[0].rsplit(':').next().unwrap_or_default().trim_end_matches(']');
+        format!("[steamid ..{}]", &account[account.len().saturating_sub(4)..])
+    })
+}
+
+/// Installs the console and file output once per process. Until `set_level` is called
+/// with the configured level, `info` is used.
+pub fn init() {
+    INIT.call_once(|| {
+        let logs_dir = Path::new(DATA_DIR).join("logs");
+        let file = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("client")
+            .filename_suffix("log")
+            .max_log_files(LOG_FILES_KEPT)
+            .build(&logs_dir);
+        let file_error = file.as_ref().err().map(|e| e.to_string());
+        let file_layer = file.ok().map(|file| {
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_target(false)
+                .with_writer(Redacting(file))
+        });
+        let result = tracing_subscriber::registry()
+            .with(filter_fn(enabled))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_target(false)
+                    .with_writer(Redacting(io::stdout)),
+            )
+            .with(file_layer)
+            .with(LuaForwardLayer)
+            .try_init();
+        if let Err(e) = result {
+            eprintln!("Failed to install logger: {}", e);
+        }
+        if let Some(e) = file_error {
+            tracing::warn!("Logging to console only, cannot write to {:?}: {}", logs_dir, e);
+        }
+    });
+}
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Starts or stops collecting WARN/ERROR lines for `take_forwarded`.
+pub fn forward_to_lua(enabled: bool) {
+    FORWARD_TO_LUA.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        FORWARDED.lock().unwrap().clear();
+    }
+}
+
+/// WARN/ERROR lines logged since the last call, oldest first.
+pub fn take_forwarded() -> Vec<(Level, String)> {
+    FORWARDED.lock().unwrap().drain(..).collect()
+}
+
+struct Redacting<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// The fmt layer writes each event with a single `write_all`, so a SteamID is never split across writes.
+struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Queues WARN/ERROR events for the Lua callback, which runs on the main thread from the Think hook.
+struct LuaForwardLayer;
+
+impl<S: Subscriber> Layer<S> for LuaForwardLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN || !FORWARD_TO_LUA.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut line = LineVisitor::default();
+        event.record(&mut line);
+        let mut forwarded = FORWARDED.lock().unwrap();
+        if forwarded.len() >= MAX_FORWARDED {
+            forwarded.pop_front();
+        }
+        forwarded.push_back((level, redact(&line.0).into_owned()));
+    }
+}
+
+#[derive(Default)]
+struct LineVisitor(String);
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_every_steam_id_form() {
+        assert_eq!(mask_steam_ids("Granted to STEAM_0:1:156722227"), "Granted to [steamid ..2227]");
+        assert_eq!(mask_steam_ids("Granted to [U:1:313444455]"), "Granted to [steamid ..4455]");
+        assert_eq!(mask_steam_ids("Granted to 76561198273710183"), "Granted to [steamid ..0183]");
+        assert_eq!(mask_steam_ids("STEAM_0:0:12 and STEAM_1:1:7"), "[steamid ..12] and [steamid ..7]");
+    }
+
+    #[test]
+    fn masks_steam_ids_next_to_punctuation() {
+        assert_eq!(
+            mask_steam_ids("who=(STEAM_0:1:1), account=\"76561198273710183\"; [U:1:2468]."),
+            "who=([steamid ..1]), account=\"[steamid ..0183]\"; [steamid ..2468]."
+        );
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        for text in [
+            "Pushing 3 message(s) to client rp-1",
+            "STEAM_ID is not set, STEAM_9:1:5 is not a SteamID",
+            "id 7656119 and 765611982737101830 are not SteamID64s",
+            "[U:2:2468] is not an individual account",
+        ] {
+            assert!(matches!(mask_steam_ids(text), Cow::Borrowed(_)), "{}", text);
+        }
+    }
+}
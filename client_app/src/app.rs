@@ -35,6 +35,10 @@ pub struct App {
     #[serde(skip)]
     pub editing_donate: Option<Donate>,
     #[serde(skip)]
+    pub editing_client: Option<ClientForm>,
+    #[serde(skip)]
+    pub managing_client: Option<ClientAction>,
+    #[serde(skip)]
     pub history_filter_steam_id: String,
     #[serde(skip)]
     pub history_filter_name: String,
@@ -55,6 +59,40 @@ pub enum Tab {
     History,
 }
 
+/// Name and notes of a client being edited in the Clients tab.
+#[derive(Clone, Default)]
+pub struct ClientForm {
+    pub uuid: String,
+    pub server_name: String,
+    pub notes: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ClientActionKind {
+    Enable,
+    Disable,
+    Revoke,
+    Delete,
+}
+
+/// What to do with undelivered messages when a client is disabled or deleted.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PendingChoice {
+    Keep,
+    Cancel,
+    Reassign,
+}
+
+/// A lifecycle change of a client, confirmed in a dialog before it is sent.
+#[derive(Clone)]
+pub struct ClientAction {
+    pub uuid: String,
+    pub server_name: String,
+    pub kind: ClientActionKind,
+    pub pending: PendingChoice,
+    pub reassign_to: String,
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
 pub struct DonateForm {
     pub client_uuid: String,
@@ -93,6 +131,8 @@ impl Default for App {
             login_status_rx,
             shutdown_tx: None,
             editing_donate: None,
+            editing_client: None,
+            managing_client: None,
             history_filter_steam_id: String::new(),
             history_filter_name: String::new(),
             history_filter_type: String::new(),
@@ -1,11 +1,11 @@
-use gmod_tcp_shared::types::{CreateRequest, Donate, Player, ClientConnection};
+use gmod_tcp_shared::types::{CreateRequest, Donate, Player, ClientConnection, ClientState, PendingMessages, UpdateClientRequest};
 use egui::{Color32, CornerRadius, Stroke, Vec2, RichText as rich};
 use reqwest::Client;
 use anyhow::Result;
 use tracing::{info, error};
 use chrono::{FixedOffset, Utc};
 
-use crate::app::{App, Tab};
+use crate::app::{App, ClientAction, ClientActionKind, ClientForm, PendingChoice, Tab};
 
 fn moscow_timezone() -> FixedOffset {
     FixedOffset::east_opt(3 * 3600).unwrap()
@@ -23,6 +23,14 @@ impl App {
             .unwrap_or_else(|_| Client::new())
     }
 
+    /// Display name of a client for pickers and history, falling back to the uuid for unknown clients.
+    fn client_label(&self, client_uuid: &str) -> String {
+        self.clients.iter()
+            .find(|client| client.uuid == client_uuid)
+            .map(|client| client.server_name.clone())
+            .unwrap_or_else(|| client_uuid.to_string())
+    }
+
     pub fn draw(&mut self, ctx: &egui::Context) {
         self.setup_style(ctx);
         
@@ -108,6 +116,46 @@ impl App {
                 self.editing_donate = Some(editing_donate);
             }
         }
+
+        if let Some(mut editing_client) = self.editing_client.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+
+            egui::Window::new("Edit Client")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    Self::draw_edit_client_modal_ui(ui, &mut editing_client, &mut should_save, &mut should_cancel);
+                });
+
+            if should_save {
+                if let Err(e) = self.update_client(editing_client) {
+                    error!("Failed to update client: {}", e);
+                }
+            } else if !should_cancel {
+                self.editing_client = Some(editing_client);
+            }
+        }
+
+        if let Some(mut managing_client) = self.managing_client.take() {
+            let mut should_confirm = false;
+            let mut should_cancel = false;
+
+            egui::Window::new("Manage Client")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    Self::draw_client_action_modal_ui(ui, &self.clients, &mut managing_client, &mut should_confirm, &mut should_cancel);
+                });
+
+            if should_confirm {
+                if let Err(e) = self.send_client_action(managing_client) {
+                    error!("Failed to manage client: {}", e);
+                }
+            } else if !should_cancel {
+                self.managing_client = Some(managing_client);
+            }
+        }
     }
 
     fn setup_style(&self, ctx: &egui::Context) {
@@ -148,19 +196,24 @@ impl App {
                     ui.vertical_centered(|ui| {
                         ui.add_space(10.0);
                         
-                        ui.label(rich::new("Client UUID").size(14.0).color(Color32::from_rgb(200, 200, 210)));
+                        ui.label(rich::new("Server").size(14.0).color(Color32::from_rgb(200, 200, 210)));
+                        let selected_client = if self.form.client_uuid.is_empty() {
+                            "Select server...".to_string()
+                        } else {
+                            self.client_label(&self.form.client_uuid)
+                        };
                         egui::ComboBox::from_id_salt("client_uuid")
                             .width(ui.available_width())
-                            .selected_text(if self.form.client_uuid.is_empty() {
-                                "Select client..."
-                            } else {
-                                &self.form.client_uuid
-                            })
+                            .selected_text(selected_client)
                             .show_ui(ui, |ui| {
                                 for client in &self.clients {
+                                    let label = match &client.map {
+                                        Some(map) => format!("{} - {} ({})", client.server_name, map, client.uuid),
+                                        None => format!("{} ({})", client.server_name, client.uuid),
+                                    };
                                     if ui.selectable_label(
                                         self.form.client_uuid == client.uuid,
-                                        format!("{} ({})", client.server_name, client.uuid)
+                                        label
                                     ).clicked() {
                                         self.form.client_uuid = client.uuid.clone();
                                     }
@@ -244,6 +297,8 @@ impl App {
         });
         ui.add_space(20.0);
 
+        let mut edit_client = None;
+        let mut client_action = None;
         egui::ScrollArea::vertical()
             .max_height(500.0)
             .show(ui, |ui| {
@@ -256,10 +311,57 @@ impl App {
                         .show(ui, |ui| {
                             ui.horizontal(|ui| {
                                 ui.vertical(|ui| {
-                                    ui.label(rich::new(&client.server_name).size(16.0).color(Color32::from_rgb(255, 0, 255)));
+                                    ui.horizontal(|ui| {
+                                        ui.label(rich::new(&client.server_name).size(16.0).color(Color32::from_rgb(255, 0, 255)));
+                                        match client.state {
+                                            ClientState::Pending => { ui.label(rich::new("Awaiting approval").size(12.0).color(Color32::from_rgb(255, 200, 0))); },
+                                            ClientState::Disabled => { ui.label(rich::new("Disabled").size(12.0).color(Color32::from_rgb(255, 80, 80))); },
+                                            _ => {},
+                                        }
+                                    });
                                     ui.label(rich::new(format!("UUID: {}", client.uuid)).size(12.0).color(Color32::from_rgb(180, 180, 190)));
+                                    if let Some(hostname) = &client.hostname {
+                                        ui.label(rich::new(format!("Hostname: {}", hostname)).size(12.0).color(Color32::from_rgb(180, 180, 190)));
+                                    }
+                                    if let Some(map) = &client.map {
+                                        ui.label(rich::new(format!("Map: {}", map)).size(12.0).color(Color32::from_rgb(180, 180, 190)));
+                                    }
+                                    if let Some(notes) = &client.notes {
+                                        ui.label(rich::new(notes).size(12.0).color(Color32::from_rgb(150, 150, 160)));
+                                    }
                                 });
                                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                    let action = |kind| ClientAction {
+                                        uuid: client.uuid.clone(),
+                                        server_name: client.server_name.clone(),
+                                        kind,
+                                        pending: if kind == ClientActionKind::Delete { PendingChoice::Cancel } else { PendingChoice::Keep },
+                                        reassign_to: String::new(),
+                                    };
+                                    if ui.button(rich::new("Delete").size(12.0).color(Color32::from_rgb(255, 80, 80))).clicked() {
+                                        client_action = Some(action(ClientActionKind::Delete));
+                                    }
+                                    if ui.button(rich::new("Revoke").size(12.0)).clicked() {
+                                        client_action = Some(action(ClientActionKind::Revoke));
+                                    }
+                                    match client.state {
+                                        ClientState::Pending => if ui.button(rich::new("Approve").size(12.0)).clicked() {
+                                            client_action = Some(action(ClientActionKind::Enable));
+                                        },
+                                        ClientState::Disabled => if ui.button(rich::new("Enable").size(12.0)).clicked() {
+                                            client_action = Some(action(ClientActionKind::Enable));
+                                        },
+                                        _ => if ui.button(rich::new("Disable").size(12.0)).clicked() {
+                                            client_action = Some(action(ClientActionKind::Disable));
+                                        },
+                                    }
+                                    if ui.button(rich::new("✏️").size(12.0)).clicked() {
+                                        edit_client = Some(ClientForm {
+                                            uuid: client.uuid.clone(),
+                                            server_name: client.server_name.clone(),
+                                            notes: client.notes.clone().unwrap_or_default(),
+                                        });
+                                    }
                                     let moscow_time = client.last_seen.with_timezone(&moscow_timezone());
                                     ui.label(rich::new(format!("Last seen: {}", moscow_time.format("%Y-%m-%d %H:%M"))).size(11.0).color(Color32::from_rgb(150, 150, 160)));
                                 });
@@ -268,6 +370,20 @@ impl App {
                     ui.add_space(10.0);
                 }
             });
+
+        if let Some(client) = edit_client {
+            self.editing_client = Some(client);
+        }
+        match client_action {
+            // Enabling loses nothing, so it is not confirmed.
+            Some(action) if action.kind == ClientActionKind::Enable => {
+                if let Err(e) = self.send_client_action(action) {
+                    error!("Failed to enable client: {}", e);
+                }
+            },
+            Some(action) => self.managing_client = Some(action),
+            None => {},
+        }
     }
 
     fn draw_history(&mut self, ui: &mut egui::Ui) {
@@ -351,6 +467,9 @@ impl App {
 
         let mut delete_id = None;
         let mut edit_donate = None;
+        let client_labels: std::collections::HashMap<&str, &str> = self.clients.iter()
+            .map(|client| (client.uuid.as_str(), client.server_name.as_str()))
+            .collect();
         
         ui.label(rich::new(format!("Showing {} of {} donates", filtered_donates.len(), self.donates.len())).size(12.0).color(Color32::from_rgb(150, 150, 160)));
         ui.add_space(10.0);
@@ -397,7 +516,7 @@ impl App {
                                                 if let Some(ref client_uuid) = donate_clone.client_uuid {
                                                     ui.add(
                                                         egui::Label::new(
-                                                            rich::new(format!("Client: {}", client_uuid)).size(10.0).color(Color32::from_rgb(120, 120, 130))
+                                                            rich::new(format!("Client: {}", client_labels.get(client_uuid.as_str()).unwrap_or(&client_uuid.as_str()))).size(10.0).color(Color32::from_rgb(120, 120, 130))
                                                         ).wrap()
                                                     );
                                                 }
@@ -580,6 +699,159 @@ impl App {
         Ok(())
     }
     
+    fn update_client(&mut self, form: ClientForm) -> Result<()> {
+        let api_url = self.api_url.clone();
+        let clients_tx = self.clients_tx.clone();
+        let api_password = self.api_password.clone();
+        let request = UpdateClientRequest {
+            server_name: Some(form.server_name.clone()),
+            notes: Some(form.notes.clone()),
+        };
+        self.async_runtime.as_ref().unwrap().spawn(async move {
+            let client = Self::create_client_with_password(&api_password);
+            match client
+                .patch(format!("{}/api/clients/{}", api_url, form.uuid))
+                .json(&request)
+                .send().await
+            {
+                Ok(resp) => {
+                    if resp.status().is_success() {
+                        info!("Client {} updated successfully", form.uuid);
+
+                        if let Ok(clients_resp) = client
+                            .get(format!("{}/api/clients", api_url))
+                            .send().await
+                        {
+                            if let Ok(clients) = clients_resp.json::<Vec<ClientConnection>>().await {
+                                let _ = clients_tx.send(clients);
+                            }
+                        }
+                    } else {
+                        error!("Failed to update client: HTTP {}", resp.status());
+                    }
+                },
+                Err(e) => error!("Failed to update client: {}", e),
+            }
+        });
+        Ok(())
+    }
+
+    fn send_client_action(&mut self, action: ClientAction) -> Result<()> {
+        let api_url = self.api_url.clone();
+        let clients_tx = self.clients_tx.clone();
+        let api_password = self.api_password.clone();
+        let pending = match action.pending {
+            PendingChoice::Keep => PendingMessages::Keep,
+            PendingChoice::Cancel => PendingMessages::Cancel,
+            PendingChoice::Reassign => PendingMessages::Reassign { to: action.reassign_to.clone() },
+        };
+        self.async_runtime.as_ref().unwrap().spawn(async move {
+            let client = Self::create_client_with_password(&api_password);
+            let url = format!("{}/api/clients/{}", api_url, action.uuid);
+            let request = match action.kind {
+                ClientActionKind::Enable => client.post(format!("{}/enable", url)),
+                ClientActionKind::Disable => client.post(format!("{}/disable", url)).json(&pending),
+                ClientActionKind::Revoke => client.post(format!("{}/revoke", url)),
+                ClientActionKind::Delete => client.delete(url).json(&pending),
+            };
+            match request.send().await {
+                Ok(resp) => {
+                    if resp.status().is_success() {
+                        info!("Client {} updated successfully", action.uuid);
+
+                        if let Ok(clients_resp) = client
+                            .get(format!("{}/api/clients", api_url))
+                            .send().await
+                        {
+                            if let Ok(clients) = clients_resp.json::<Vec<ClientConnection>>().await {
+                                let _ = clients_tx.send(clients);
+                            }
+                        }
+                    } else {
+                        error!("Failed to manage client {}: HTTP {}", action.uuid, resp.status());
+                    }
+                },
+                Err(e) => error!("Failed to manage client {}: {}", action.uuid, e),
+            }
+        });
+        Ok(())
+    }
+
+    fn draw_client_action_modal_ui(ui: &mut egui::Ui, clients: &[ClientConnection], action: &mut ClientAction, should_confirm: &mut bool, should_cancel: &mut bool) {
+        ui.vertical(|ui| {
+            let (title, description) = match action.kind {
+                ClientActionKind::Enable => ("Enable Client", "The client will receive messages again."),
+                ClientActionKind::Disable => ("Disable Client", "The server stops talking to the client until it is enabled again."),
+                ClientActionKind::Revoke => ("Revoke Credentials", "The client has to register again to get a new secret."),
+                ClientActionKind::Delete => ("Delete Client", "The client is removed, its donate history stays."),
+            };
+            ui.heading(rich::new(title).size(20.0).color(Color32::from_rgb(255, 0, 255)));
+            ui.label(rich::new(format!("{} ({})", action.server_name, action.uuid)).size(12.0).color(Color32::from_rgb(180, 180, 190)));
+            ui.add_space(10.0);
+            ui.label(rich::new(description).size(14.0).color(Color32::from_rgb(200, 200, 210)));
+
+            if matches!(action.kind, ClientActionKind::Disable | ClientActionKind::Delete) {
+                ui.add_space(15.0);
+                ui.label(rich::new("Pending messages").size(14.0).color(Color32::from_rgb(200, 200, 210)));
+                if action.kind == ClientActionKind::Disable {
+                    ui.radio_value(&mut action.pending, PendingChoice::Keep, "Keep until enabled");
+                }
+                ui.radio_value(&mut action.pending, PendingChoice::Cancel, "Cancel");
+                ui.radio_value(&mut action.pending, PendingChoice::Reassign, "Reassign to");
+                if action.pending == PendingChoice::Reassign {
+                    let selected = clients.iter()
+                        .find(|client| client.uuid == action.reassign_to)
+                        .map(|client| client.server_name.clone())
+                        .unwrap_or_else(|| "Select server...".to_string());
+                    egui::ComboBox::from_id_salt("reassign_to")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            for client in clients.iter().filter(|client| client.uuid != action.uuid) {
+                                ui.selectable_value(&mut action.reassign_to, client.uuid.clone(), format!("{} ({})", client.server_name, client.uuid));
+                            }
+                        });
+                }
+            }
+            ui.add_space(20.0);
+
+            ui.horizontal(|ui| {
+                let can_confirm = action.pending != PendingChoice::Reassign || !action.reassign_to.is_empty();
+                if ui.add_enabled(can_confirm, egui::Button::new(rich::new("Confirm").size(16.0).color(Color32::WHITE))).clicked() {
+                    *should_confirm = true;
+                }
+                if ui.button(rich::new("Cancel").size(16.0)).clicked() {
+                    *should_cancel = true;
+                }
+            });
+        });
+    }
+
+    fn draw_edit_client_modal_ui(ui: &mut egui::Ui, form: &mut ClientForm, should_save: &mut bool, should_cancel: &mut bool) {
+        ui.vertical(|ui| {
+            ui.heading(rich::new("Edit Client").size(20.0).color(Color32::from_rgb(255, 0, 255)));
+            ui.label(rich::new(format!("UUID: {}", form.uuid)).size(12.0).color(Color32::from_rgb(180, 180, 190)));
+            ui.add_space(15.0);
+
+            ui.label(rich::new("Server Name").size(14.0).color(Color32::from_rgb(200, 200, 210)));
+            ui.text_edit_singleline(&mut form.server_name);
+            ui.add_space(10.0);
+
+            ui.label(rich::new("Notes").size(14.0).color(Color32::from_rgb(200, 200, 210)));
+            ui.text_edit_multiline(&mut form.notes);
+            ui.add_space(20.0);
+
+            ui.horizontal(|ui| {
+                let can_save = !form.server_name.trim().is_empty();
+                if ui.add_enabled(can_save, egui::Button::new(rich::new("Save").size(16.0).color(Color32::WHITE))).clicked() {
+                    *should_save = true;
+                }
+                if ui.button(rich::new("Cancel").size(16.0)).clicked() {
+                    *should_cancel = true;
+                }
+            });
+        });
+    }
+
     fn draw_edit_donate_modal_ui(ui: &mut egui::Ui, donate: &mut Donate, should_save: &mut bool, should_cancel: &mut bool) {
         ui.vertical(|ui| {
             ui.heading(rich::new("Edit Donate").size(20.0).color(Color32::from_rgb(255, 0, 255)));
@@ -650,7 +922,8 @@ impl App {
                 .send().await
             {
                 Ok(response) => {
-                    let status_code = response.headers().clone().get("content-length").unwrap().to_str().unwrap().parse::<u32>().unwrap();
+                    // The auth middleware answers 401 for a wrong or missing password.
+                    let logged = response.status().is_success();
                     if let Ok(clients) = response.json::<Vec<ClientConnection>>().await {
                         info!("Loaded {} clients", clients.len());
                         if let Err(e) = clients_tx.send(clients) {
@@ -659,8 +932,7 @@ impl App {
                     } else {
                         error!("Failed to parse clients response");
                     }
-                    println!("status_code: {:?}", status_code);
-                    if let Err(e) = login_status_tx.send(status_code == 150) {
+                    if let Err(e) = login_status_tx.send(logged) {
                         error!("Error sending login status: {}", e);
                     }
                 },
@@ -0,0 +1,219 @@
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use gmod_tcp_shared::framing::DEFAULT_MAX_FRAME_SIZE;
+use gmod_tcp_shared::tls::parse_fingerprint;
+use tracing::info;
+
+const CONFIG_FILE: &str = "config.json";
+/// Under half of the server's default `SESSION_IDLE_SECS` of 90, so one late heartbeat does not close the session.
+pub const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 45;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl From<LogLevel> for tracing::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Server addresses as `host:port`.
+    pub servers: Vec<String>,
+    /// Client identifier, generated on first start when missing.
+    pub uuid: Option<String>,
+    /// Name shown for this game server in the donate manager.
+    pub server_name: Option<String>,
+    /// Upper bound of the reconnect backoff, and so of the fallback poll interval.
+    pub poll_interval_secs: u64,
+    /// Ping interval of the push session, at most `MAX_HEARTBEAT_INTERVAL_SECS`. The server
+    /// closes a session it has not heard from for `SESSION_IDLE_SECS`.
+    pub heartbeat_interval_secs: u64,
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    pub max_frame_size: usize,
+    /// SHA-256 fingerprint of the server certificate. TLS is used only when it is set.
+    pub tls_fingerprint: Option<String>,
+    pub log_level: LogLevel,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            servers: vec!["127.0.0.1:25565".to_string()],
+            uuid: None,
+            server_name: None,
+            poll_interval_secs: 600,
+            heartbeat_interval_secs: 30,
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            tls_fingerprint: None,
+            log_level: LogLevel::Info,
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(CONFIG_FILE)
+    }
+
+    /// Loads and validates `config.json` in `data_dir`, creating it from the legacy `host.txt`,
+    /// `uuid.txt` and `tls.txt` on first start, and filling in a generated uuid when it has none.
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = Self::path(data_dir);
+        let mut config = if path.exists() {
+            serde_json::from_slice::<Self>(&fs::read(&path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid config {:?}: {}", path, e))?
+        } else {
+            let config = Self::from_legacy_files(data_dir)?;
+            info!("Created {:?} from the legacy settings files", path);
+            config
+        };
+        config.validate().map_err(|e| anyhow::anyhow!("Invalid config {:?}: {}", path, e))?;
+        if config.uuid.is_none() {
+            let uuid = Uuid::new_v4().to_string();
+            info!("Generated new UUID: {} (saved to {:?})", uuid, path);
+            config.uuid = Some(uuid);
+            config.save(data_dir)?;
+        } else if !path.exists() {
+            config.save(data_dir)?;
+        }
+        Ok(config)
+    }
+
+    fn from_legacy_files(data_dir: &Path) -> Result<Self> {
+        let read = |name: &str| -> Result<Option<String>> {
+            let path = data_dir.join(name);
+            if !path.exists() {
+                return Ok(None);
+            }
+            let value = fs::read_to_string(&path)?.trim().to_string();
+            Ok(Some(value).filter(|value| !value.is_empty()))
+        };
+        let mut config = Self::default();
+        if let Some(host) = read("host.txt")? {
+            config.servers = vec![host];
+        }
+        config.uuid = read("uuid.txt")?;
+        config.tls_fingerprint = read("tls.txt")?;
+        Ok(config)
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        fs::create_dir_all(data_dir)?;
+        fs::write(Self::path(data_dir), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Checks every field and reports all problems at once.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.servers.is_empty() {
+            problems.push("servers must list at least one host:port".to_string());
+        }
+        for (i, server) in self.servers.iter().enumerate() {
+            if let Err(e) = parse_server(server) {
+                problems.push(format!("servers[{}] {:?}: {}", i, server, e));
+            }
+        }
+        if self.uuid.as_deref().is_some_and(|uuid| uuid.trim().is_empty()) {
+            problems.push("uuid must not be empty, remove it to generate a new one".to_string());
+        }
+        for (name, value) in [
+            ("poll_interval_secs", self.poll_interval_secs),
+            ("heartbeat_interval_secs", self.heartbeat_interval_secs),
+            ("connect_timeout_secs", self.connect_timeout_secs),
+            ("read_timeout_secs", self.read_timeout_secs),
+        ] {
+            if value == 0 {
+                problems.push(format!("{} must be at least 1", name));
+            }
+        }
+        if self.heartbeat_interval_secs > MAX_HEARTBEAT_INTERVAL_SECS {
+            problems.push(format!(
+                "heartbeat_interval_secs must be at most {}, the server closes idle sessions",
+                MAX_HEARTBEAT_INTERVAL_SECS
+            ));
+        }
+        if self.max_frame_size < 1024 {
+            problems.push("max_frame_size must be at least 1024".to_string());
+        }
+        if let Some(fingerprint) = &self.tls_fingerprint {
+            if let Err(e) = parse_fingerprint(fingerprint) {
+                problems.push(format!("tls_fingerprint: {}", e));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(problems.join("; ")))
+        }
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_secs(self.read_timeout_secs)
+    }
+}
+
+/// Splits `host:port`, rejecting a missing host or an invalid port.
+pub fn parse_server(server: &str) -> Result<(String, u16)> {
+    let (host, port) = server.rsplit_once(':').ok_or_else(|| anyhow::anyhow!("expected host:port"))?;
+    if host.is_empty() {
+        return Err(anyhow::anyhow!("host is empty"));
+    }
+    let port = port.parse::<u16>().map_err(|e| anyhow::anyhow!("invalid port {:?}: {}", port, e))?;
+    Ok((host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        ClientConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn bounds_heartbeat_interval() {
+        let config = ClientConfig { heartbeat_interval_secs: MAX_HEARTBEAT_INTERVAL_SECS, ..Default::default() };
+        config.validate().unwrap();
+        for heartbeat_interval_secs in [0, MAX_HEARTBEAT_INTERVAL_SECS + 1, 90] {
+            let config = ClientConfig { heartbeat_interval_secs, ..Default::default() };
+            let error = config.validate().unwrap_err().to_string();
+            assert!(error.contains("heartbeat_interval_secs"), "{}: {}", heartbeat_interval_secs, error);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::parse_server;
+
+/// Backoff after the first failed connect, doubled on every further failure.
+const MIN_ENDPOINT_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Health {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    health: Vec<Health>,
+    active: Option<usize>,
+}
+
+/// Ordered server endpoints. Earlier entries are preferred; an endpoint that fails to connect
+/// is skipped until its backoff runs out, so the client falls back to the next one and returns
+/// to the primary once it is due for a retry.
+#[derive(Debug)]
+pub struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    state: Mutex<State>,
+    max_backoff: Duration,
+}
+
+impl Endpoints {
+    pub fn new(servers: &[String], max_backoff: Duration) -> Result<Self> {
+        let endpoints = servers
+            .iter()
+            .map(|server| parse_server(server).map(|(host, port)| Endpoint { host, port }))
+            .collect::<Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return Err(anyhow::anyhow!("No server endpoints configured"));
+        }
+        let state = State {
+            health: vec![Health::default(); endpoints.len()],
+            active: None,
+        };
+        Ok(Self {
+            endpoints,
+            state: Mutex::new(state),
+            max_backoff,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> &Endpoint {
+        &self.endpoints[index]
+    }
+
+    /// Endpoints worth trying now, in priority order. When every endpoint is backing off,
+    /// the one due soonest is tried anyway so a request never fails without a connect attempt.
+    pub fn candidates(&self) -> Vec<usize> {
+        self.candidates_at(Instant::now())
+    }
+
+    fn candidates_at(&self, now: Instant) -> Vec<usize> {
+        let state = self.state.lock().unwrap();
+        let ready = (0..self.endpoints.len())
+            .filter(|&index| state.health[index].retry_at.is_none_or(|retry_at| retry_at <= now))
+            .collect::<Vec<_>>();
+        if !ready.is_empty() {
+            return ready;
+        }
+        (0..self.endpoints.len())
+            .min_by_key(|&index| state.health[index].retry_at)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn mark_success(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.health[index] = Health::default();
+        if state.active != Some(index) {
+            info!("Active server is now {}", self.endpoints[index]);
+            state.active = Some(index);
+        }
+    }
+
+    pub fn mark_failure(&self, index: usize) {
+        let mut state = self.state.lock().unwrap();
+        let health = &mut state.health[index];
+        health.failures += 1;
+        let backoff = backoff(health.failures, self.max_backoff);
+        health.retry_at = Some(Instant::now() + backoff);
+        warn!("Server {} failed {} time(s) in a row, retrying it in {:?}", self.endpoints[index], health.failures, backoff);
+        if state.active == Some(index) {
+            state.active = None;
+        }
+    }
+
+    /// The endpoint of the last successful connect, unless it has failed since.
+    pub fn active(&self) -> Option<&Endpoint> {
+        self.state.lock().unwrap().active.map(|index| &self.endpoints[index])
+    }
+}
+
+/// How long an endpoint is skipped after `failures` failed connects in a row.
+fn backoff(failures: u32, max_backoff: Duration) -> Duration {
+    MIN_ENDPOINT_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures - 1))
+        .min(max_backoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    fn endpoints() -> Endpoints {
+        let servers = ["primary:25565", "backup:25566", "last:25567"].map(str::to_string);
+        Endpoints::new(&servers, MAX_BACKOFF).unwrap()
+    }
+
+    #[test]
+    fn parses_servers_in_order() {
+        let endpoints = endpoints();
+        assert_eq!(endpoints.get(1).to_string(), "backup:25566");
+        assert_eq!(endpoints.candidates(), vec![0, 1, 2]);
+        assert!(Endpoints::new(&[], MAX_BACKOFF).is_err());
+        assert!(Endpoints::new(&["no port".to_string()], MAX_BACKOFF).is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let backoffs: Vec<u64> = (1..=7).map(|failures| backoff(failures, MAX_BACKOFF).as_secs()).collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(backoff(u32::MAX, MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn falls_back_and_returns_to_the_primary() {
+        let endpoints = endpoints();
+        endpoints.mark_success(0);
+        assert_eq!(endpoints.active().unwrap().host, "primary");
+
+        endpoints.mark_failure(0);
+        assert!(endpoints.active().is_none());
+        let now = Instant::now();
+        assert_eq!(endpoints.candidates_at(now), vec![1, 2]);
+        endpoints.mark_success(1);
+        assert_eq!(endpoints.active().unwrap().host, "backup");
+
+        // Once its backoff ran out the primary is preferred again.
+        assert_eq!(endpoints.candidates_at(now + MIN_ENDPOINT_BACKOFF), vec![0, 1, 2]);
+        endpoints.mark_success(0);
+        assert_eq!(endpoints.active().unwrap().host, "primary");
+    }
+
+    #[test]
+    fn tries_the_endpoint_due_soonest_when_all_back_off() {
+        let endpoints = endpoints();
+        endpoints.mark_failure(1);
+        endpoints.mark_failure(0);
+        endpoints.mark_failure(0);
+        endpoints.mark_failure(2);
+        endpoints.mark_failure(2);
+        assert_eq!(endpoints.candidates_at(Instant::now()), vec![1]);
+    }
+}
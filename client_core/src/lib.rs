@@ -0,0 +1,13 @@
+//! Headless client for the donate server: registration, the push session with its polling
+//! fallback, acknowledgements and the on-disk config. The GMod module is a thin layer over it.
+pub mod config;
+pub mod endpoints;
+pub mod queue;
+pub mod status;
+pub mod tcp;
+mod tls;
+
+pub use config::{ClientConfig, LogLevel};
+pub use queue::MessageQueue;
+pub use status::ClientStatus;
+pub use tcp::{MessageHandler, TcpClient};
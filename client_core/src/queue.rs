@@ -0,0 +1,237 @@
+use anyhow::Result;
+use gmod_tcp_shared::types::Message;
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn, error};
+
+use crate::tcp::MessageHandler;
+
+/// How many handed-out message ids are remembered to drop re-deliveries.
+const MAX_SEEN_IDS: usize = 1024;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Journal {
+    pending: Vec<Message>,
+    seen: VecDeque<u64>,
+}
+
+/// Messages received from the server but not yet handed to Lua, journaled to disk so they survive
+/// a map change or crash. Ids are remembered after hand-out so a re-delivered message is not applied twice.
+pub struct MessageQueue {
+    path: PathBuf,
+    journal: Mutex<Journal>,
+}
+
+impl MessageQueue {
+    /// Opens the journal at `path`, replaying whatever was still pending when the module last stopped.
+    pub fn open(path: &Path) -> Self {
+        let journal = match Self::read_journal(path) {
+            Ok(journal) => journal,
+            Err(e) => {
+                warn!("Failed to read message journal {:?}, starting empty: {}", path, e);
+                Journal::default()
+            }
+        };
+        if !journal.pending.is_empty() {
+            info!("Replaying {} message(s) from {:?}", journal.pending.len(), path);
+        }
+        Self {
+            path: path.to_path_buf(),
+            journal: Mutex::new(journal),
+        }
+    }
+
+    fn read_journal(path: &Path) -> Result<Journal> {
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Queues new messages, skipping ones already queued or handed out. Returns how many were added.
+    pub fn push(&self, messages: Vec<Message>) -> usize {
+        let mut journal = self.journal.lock().unwrap();
+        let mut added = 0;
+        for message in messages {
+            let duplicate = journal.seen.contains(&message.id)
+                || journal.pending.iter().any(|pending| pending.id == message.id);
+            if duplicate {
+                info!("Skipping duplicate message {}", message.id);
+                continue;
+            }
+            journal.pending.push(message);
+            added += 1;
+        }
+        if added > 0 {
+            self.persist(&journal);
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.journal.lock().unwrap().pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.journal.lock().unwrap().pending.is_empty()
+    }
+
+    /// Removes every pending message for hand-out to Lua.
+    pub fn take_all(&self) -> Vec<Message> {
+        self.take_matching(|_| true)
+    }
+
+    /// Removes the pending messages matching `filter` for hand-out to Lua, keeping the rest queued.
+    pub fn take_matching(&self, filter: impl Fn(&Message) -> bool) -> Vec<Message> {
+        let mut journal = self.journal.lock().unwrap();
+        let (taken, kept): (Vec<Message>, Vec<Message>) = journal.pending.drain(..).partition(|message| filter(message));
+        journal.pending = kept;
+        if taken.is_empty() {
+            return taken;
+        }
+        for message in &taken {
+            journal.seen.push_back(message.id);
+        }
+        let overflow = journal.seen.len().saturating_sub(MAX_SEEN_IDS);
+        journal.seen.drain(..overflow);
+        self.persist(&journal);
+        taken
+    }
+
+    /// Forgets a handed-out id so the server may deliver the message again, e.g. after a nack.
+    pub fn forget(&self, id: u64) {
+        let mut journal = self.journal.lock().unwrap();
+        let before = journal.seen.len();
+        journal.seen.retain(|seen| *seen != id);
+        if journal.seen.len() != before {
+            self.persist(&journal);
+        }
+    }
+
+    fn persist(&self, journal: &Journal) {
+        if let Err(e) = self.write_journal(journal) {
+            error!("Failed to write message journal {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Writes to a temporary file first so a crash mid-write never leaves a truncated journal.
+    fn write_journal(&self, journal: &Journal) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(journal)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl MessageHandler for MessageQueue {
+    fn on_messages(&self, messages: Vec<Message>) {
+        let added = self.push(messages);
+        info!("Added {} message(s) to queue, {} waiting", added, self.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    /// A journal path in the temp dir, removed on drop.
+    struct TempJournal(PathBuf);
+
+    impl TempJournal {
+        fn new(name: &str) -> Self {
+            TempJournal(std::env::temp_dir().join(format!("gmod_tcp_queue_{}_{}.json", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempJournal {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn message(id: u64) -> Message {
+        Message {
+            id,
+            client_uuid: "rp-1".to_string(),
+            message_type: "donate".to_string(),
+            message_data: serde_json::json!({ "id": id }),
+            created_at: Utc::now(),
+            delivered_at: None,
+            status: "pending".to_string(),
+            outcome: None,
+            failure_reason: None,
+        }
+    }
+
+    fn ids(messages: &[Message]) -> Vec<u64> {
+        messages.iter().map(|message| message.id).collect()
+    }
+
+    #[test]
+    fn replays_pending_messages_from_the_journal() {
+        let journal = TempJournal::new("replay");
+        let queue = MessageQueue::open(&journal.0);
+        assert_eq!(queue.push(vec![message(1), message(2), message(3)]), 3);
+        assert_eq!(ids(&queue.take_matching(|message| message.id == 2)), vec![2]);
+        drop(queue);
+
+        let reopened = MessageQueue::open(&journal.0);
+        assert_eq!(reopened.len(), 2);
+        // The handed-out id is remembered across the restart too.
+        assert_eq!(reopened.push(vec![message(2)]), 0);
+        assert_eq!(ids(&reopened.take_all()), vec![1, 3]);
+        assert!(reopened.is_empty());
+    }
+
+    #[test]
+    fn starts_empty_on_a_broken_journal() {
+        let journal = TempJournal::new("broken");
+        fs::write(&journal.0, b"{\"pending\": [").unwrap();
+        let queue = MessageQueue::open(&journal.0);
+        assert!(queue.is_empty());
+        assert_eq!(queue.push(vec![message(1)]), 1);
+        assert_eq!(MessageQueue::open(&journal.0).len(), 1);
+    }
+
+    #[test]
+    fn drops_redeliveries() {
+        let journal = TempJournal::new("dedupe");
+        let queue = MessageQueue::open(&journal.0);
+        assert_eq!(queue.push(vec![message(1), message(1)]), 1);
+        assert_eq!(queue.push(vec![message(1), message(2)]), 1);
+        queue.take_all();
+        assert_eq!(queue.push(vec![message(1), message(2)]), 0);
+    }
+
+    #[test]
+    fn forgets_nacked_messages() {
+        let journal = TempJournal::new("forget");
+        let queue = MessageQueue::open(&journal.0);
+        queue.push(vec![message(1), message(2)]);
+        queue.take_all();
+        queue.forget(1);
+        queue.forget(42);
+        assert_eq!(queue.push(vec![message(1), message(2)]), 1);
+        assert_eq!(ids(&queue.take_all()), vec![1]);
+    }
+
+    #[test]
+    fn remembers_only_the_latest_ids() {
+        let journal = TempJournal::new("evict");
+        let queue = MessageQueue::open(&journal.0);
+        let total = MAX_SEEN_IDS as u64 + 10;
+        queue.push((1..=total).map(message).collect());
+        assert_eq!(queue.take_all().len(), total as usize);
+
+        // The oldest ids were evicted, the newest are still dropped.
+        assert_eq!(queue.push((1..=10).map(message).collect()), 10);
+        assert_eq!(queue.push(vec![message(11), message(total)]), 0);
+    }
+}
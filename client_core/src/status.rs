@@ -0,0 +1,64 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Snapshot of what the client has been doing, for `GModTCPStatus()`. Times are unix seconds like `os.time()`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientStatus {
+    pub registered: bool,
+    pub session_active: bool,
+    pub last_poll_time: Option<u64>,
+    pub last_poll_ok: Option<bool>,
+    pub last_poll_messages: usize,
+    pub reconnects: u64,
+    pub server_version: Option<String>,
+    pub protocol_version: Option<u32>,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct StatusTracker {
+    status: Mutex<ClientStatus>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+impl StatusTracker {
+    pub fn snapshot(&self) -> ClientStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn set_registered(&self, registered: bool) {
+        self.status.lock().unwrap().registered = registered;
+    }
+
+    pub fn set_session_active(&self, active: bool) {
+        self.status.lock().unwrap().session_active = active;
+    }
+
+    pub fn set_server_version(&self, server_version: String, protocol_version: u32) {
+        let mut status = self.status.lock().unwrap();
+        status.server_version = Some(server_version);
+        status.protocol_version = Some(protocol_version);
+    }
+
+    /// Records a poll that returned `messages`, or failed when `None`.
+    pub fn record_poll(&self, messages: Option<usize>) {
+        let mut status = self.status.lock().unwrap();
+        status.last_poll_time = Some(now());
+        status.last_poll_ok = Some(messages.is_some());
+        status.last_poll_messages = messages.unwrap_or_default();
+    }
+
+    pub fn record_reconnect(&self) {
+        self.status.lock().unwrap().reconnects += 1;
+    }
+
+    pub fn record_error(&self, error: String) {
+        let mut status = self.status.lock().unwrap();
+        status.last_error = Some(error);
+        status.last_error_time = Some(now());
+    }
+}
@@ -0,0 +1,438 @@
+use anyhow::Result;
+use tokio::net::TcpStream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::mpsc,
+    time::{Duration, Instant}
+};
+use rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+use tokio_util::sync::CancellationToken;
+use gmod_tcp_shared::framing::{FrameCodec, FrameError};
+use gmod_tcp_shared::protocol::{ClientFrame, Request, Response, MIN_PROTOCOL_VERSION};
+use gmod_tcp_shared::types::{Message, ServerInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info, warn};
+
+use std::fs;
+
+use crate::config::ClientConfig;
+use crate::endpoints::{Endpoint, Endpoints};
+use crate::status::{ClientStatus, StatusTracker};
+use crate::tls;
+
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A connection to the server, either plain TCP or TLS.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Receives the messages of every poll and push. Runs on the client's runtime, so it should hand
+/// them off rather than do slow work inline.
+pub trait MessageHandler: Send + Sync {
+    fn on_messages(&self, messages: Vec<Message>);
+}
+
+pub struct TcpClient {
+    pub client_uuid: String,
+    endpoints: Endpoints,
+    secret_path: PathBuf,
+    secret: RwLock<Option<String>>,
+    server_info: RwLock<ServerInfo>,
+    tls_connector: Option<TlsConnector>,
+    codec: FrameCodec,
+    connect_timeout: Duration,
+    heartbeat_interval: Duration,
+    max_reconnect_backoff: Duration,
+    status: StatusTracker,
+    /// Set until a registration succeeds and again when the server says the credentials are gone.
+    needs_registration: AtomicBool,
+    shutdown: CancellationToken,
+}
+
+impl std::fmt::Debug for TcpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TcpClient")
+            .field("client_uuid", &self.client_uuid)
+            .field("endpoints", &self.endpoints)
+            .field("tls", &self.tls_connector.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TcpClient {
+    /// Creates a client for `config`. The secret the server issues on registration is kept in `data_dir`.
+    pub fn new(config: &ClientConfig, data_dir: &Path) -> Result<Self> {
+        config.validate()?;
+        let client_uuid = config.uuid.clone().ok_or_else(|| anyhow::anyhow!("Config has no uuid"))?;
+        let endpoints = Endpoints::new(&config.servers, config.poll_interval())?;
+        let secret_path = data_dir.join("secret.txt");
+        let secret = TcpClient::load_secret(&secret_path)?;
+        let tls_connector = match &config.tls_fingerprint {
+            Some(fingerprint) => {
+                info!("TLS enabled, pinned server fingerprint {}", fingerprint);
+                Some(tls::connector_for_fingerprint(fingerprint)?)
+            }
+            None => None,
+        };
+        Ok(Self { 
+            client_uuid,
+            endpoints,
+            secret_path,
+            secret: RwLock::new(secret.clone()),
+            server_info: RwLock::new(ServerInfo {
+                server_name: config.server_name.clone(),
+                ..ServerInfo::default()
+            }),
+            tls_connector,
+            codec: FrameCodec::new(config.max_frame_size, Some(config.read_timeout())),
+            connect_timeout: config.connect_timeout(),
+            heartbeat_interval: config.heartbeat_interval(),
+            max_reconnect_backoff: config.poll_interval(),
+            status: StatusTracker::default(),
+            needs_registration: AtomicBool::new(secret.is_none()),
+            shutdown: CancellationToken::new(),
+        })
+    }
+
+    fn load_secret(path: &Path) -> Result<Option<String>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let secret = fs::read_to_string(path)?.trim().to_string();
+        if secret.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(secret))
+    }
+
+    fn save_secret(&self, secret: String) -> Result<()> {
+        if let Some(secret_dir) = self.secret_path.parent() {
+            fs::create_dir_all(secret_dir)?;
+        }
+        fs::write(&self.secret_path, &secret)?;
+        *self.secret.write().unwrap() = Some(secret);
+        Ok(())
+    }
+
+    /// Builds a frame signed with the client secret, if one has been issued.
+    fn new_frame(&self, request: Request) -> ClientFrame {
+        let secret = self.secret.read().unwrap();
+        ClientFrame::new(self.client_uuid.clone(), request, secret.as_deref())
+    }
+
+    /// Sets what `register` reports about the game server, like its map and hostname.
+    pub fn set_server_info(&self, info: ServerInfo) {
+        *self.server_info.write().unwrap() = info;
+    }
+
+    pub fn status(&self) -> ClientStatus {
+        self.status.snapshot()
+    }
+
+    /// The server the client last connected to successfully, if it has not failed since.
+    pub fn active_endpoint(&self) -> Option<&Endpoint> {
+        self.endpoints.active()
+    }
+
+    /// Connects to the first reachable endpoint, in priority order, skipping ones still backing off.
+    pub async fn connect(&self) -> Result<Box<dyn Stream>> {
+        let mut errors = Vec::new();
+        for index in self.endpoints.candidates() {
+            match self.connect_to(self.endpoints.get(index)).await {
+                Ok(stream) => {
+                    self.endpoints.mark_success(index);
+                    return Ok(stream);
+                }
+                Err(e) => {
+                    warn!("{}", e);
+                    self.endpoints.mark_failure(index);
+                    errors.push(e.to_string());
+                }
+            }
+        }
+        Err(anyhow::anyhow!("No server reachable: {}", errors.join("; ")))
+    }
+
+    async fn connect_to(&self, endpoint: &Endpoint) -> Result<Box<dyn Stream>> {
+        let addr = endpoint.to_string();
+        debug!("Connecting to server at {}", addr);
+        let connect_future = TcpStream::connect(&addr);
+        let stream = match tokio::time::timeout(self.connect_timeout, connect_future).await {
+            Ok(Ok(stream)) => {
+                debug!("Successfully connected to {}", addr);
+                stream
+            }
+            Ok(Err(e)) => {
+                let os_error = e.raw_os_error();
+                return Err(anyhow::anyhow!("Connection failed to {}: {} (os error: {:?})", addr, e, os_error));
+            }
+            Err(_) => {
+                return Err(anyhow::anyhow!("Connection timeout after {:?} to {}", self.connect_timeout, addr));
+            }
+        };
+        let Some(connector) = &self.tls_connector else {
+            return Ok(Box::new(stream));
+        };
+        let server_name = ServerName::try_from(endpoint.host.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid server name {}: {}", endpoint.host, e))?;
+        match tokio::time::timeout(self.connect_timeout, connector.connect(server_name, stream)).await {
+            Ok(Ok(tls_stream)) => Ok(Box::new(tls_stream)),
+            Ok(Err(e)) => Err(anyhow::anyhow!("TLS handshake with {} failed: {}", addr, e)),
+            Err(_) => Err(anyhow::anyhow!("TLS handshake timeout after {:?} to {}", self.connect_timeout, addr)),
+        }
+    }
+
+    async fn read_message<R: AsyncRead + Unpin>(&self, socket: &mut R) -> Result<Vec<u8>> {
+        Ok(self.codec.read_frame(socket).await?)
+    }
+
+    async fn write_message<W: AsyncWrite + Unpin>(&self, socket: &mut W, data: &[u8]) -> Result<()> {
+        Ok(self.codec.write_frame(socket, data).await?)
+    }
+
+    /// Checks that the server speaks a protocol version this client understands.
+    pub async fn hello(&self) -> Result<()> {
+        let Response::Hello { protocol_version, server_version } = self.send_request(Request::Hello).await? else {
+            return Err(anyhow::anyhow!("Unexpected reply to hello"));
+        };
+        if protocol_version < MIN_PROTOCOL_VERSION {
+            return Err(anyhow::anyhow!(
+                "Server {} speaks protocol version {}, this module requires at least {}",
+                server_version, protocol_version, MIN_PROTOCOL_VERSION
+            ));
+        }
+        info!("Server {} speaks protocol version {}", server_version, protocol_version);
+        self.status.set_server_version(server_version, protocol_version);
+        Ok(())
+    }
+
+    /// Registers with the server, saving the secret it issues. Until this succeeds the listen loop retries it.
+    pub async fn register(&self) -> Result<()> {
+        let result = async {
+            debug!("Connecting to server for registration");
+            self.hello().await?;
+            let info = self.server_info.read().unwrap().clone();
+            let Response::Registered { secret } = self.send_request(Request::Register(info)).await? else {
+                return Err(anyhow::anyhow!("Unexpected reply to register"));
+            };
+            if let Some(secret) = secret {
+                self.save_secret(secret)?;
+                info!("Received client credentials (saved to {:?})", self.secret_path);
+            }
+            Ok(())
+        }.await;
+        self.needs_registration.store(result.is_err(), Ordering::Relaxed);
+        self.status.set_registered(result.is_ok());
+        result
+    }
+
+    /// Notes a refusal, so the listen loop registers again when the server says that would help.
+    fn check_unauthorized(&self, response: &Response) {
+        if let Response::Unauthorized { register: true, .. } = response {
+            warn!("Server no longer accepts the client credentials, registering again");
+            self.needs_registration.store(true, Ordering::Relaxed);
+            self.status.set_registered(false);
+        }
+    }
+
+    /// Stops the listen loop, dropping any session or poll it has in flight.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Keeps a push session open in the background, polling while it is down, until `shutdown`.
+    pub async fn listen(self: &Arc<Self>, handler: Arc<dyn MessageHandler>) -> Result<()> {
+        let clone_self = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = clone_self.shutdown.cancelled() => info!("Listen loop stopped"),
+                _ = clone_self.listen_loop(handler.as_ref()) => {}
+            }
+        });
+        Ok(())
+    }
+
+    async fn listen_loop(&self, handler: &dyn MessageHandler) {
+        let mut backoff = MIN_RECONNECT_BACKOFF;
+        loop {
+            if self.needs_registration.load(Ordering::Relaxed) {
+                if let Err(e) = self.register().await {
+                    warn!("Registration failed, retrying in {:?}: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_reconnect_backoff);
+                    continue;
+                }
+                info!("Client registered successfully");
+                backoff = MIN_RECONNECT_BACKOFF;
+            }
+            match self.run_session(handler).await {
+                Ok(()) => backoff = MIN_RECONNECT_BACKOFF,
+                Err(e) => {
+                    warn!("Push session unavailable: {}", e);
+                    self.status.record_error(format!("Push session unavailable: {}", e));
+                }
+            }
+            self.status.set_session_active(false);
+            // Poll while the session is down so nothing waits for the reconnect.
+            match self.find_messages().await {
+                Ok(messages) if !messages.is_empty() => handler.on_messages(messages),
+                Ok(_) => {}
+                Err(e) => warn!("Error finding messages: {}", e),
+            }
+            info!("Reconnecting push session in {:?}", backoff);
+            tokio::time::sleep(backoff).await;
+            self.status.record_reconnect();
+            backoff = (backoff * 2).min(self.max_reconnect_backoff);
+        }
+    }
+
+    /// Keeps a push session open until it drops. Returns an error only if the session could not be established.
+    pub async fn run_session(&self, handler: &dyn MessageHandler) -> Result<()> {
+        let stream = self.connect().await?;
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let frame = self.new_frame(Request::Subscribe);
+        self.write_message(&mut writer, &serde_json::to_vec(&frame)?).await?;
+        let response: Response = serde_json::from_slice(&self.read_message(&mut reader).await?)?;
+        self.check_unauthorized(&response);
+        let Response::Subscribed { .. } = response.into_result()
+            .map_err(|e| anyhow::anyhow!("Failed to subscribe: {}", e))? else {
+            return Err(anyhow::anyhow!("Unexpected reply to subscribe"));
+        };
+        info!("Push session established");
+        self.status.set_session_active(true);
+
+        let (frames_tx, mut frames_rx) = mpsc::channel::<Vec<u8>>(8);
+        // The heartbeat watchdog below detects a stalled server, so the reader waits indefinitely.
+        let codec = self.codec.without_read_timeout();
+        let reader_task = tokio::spawn(async move {
+            loop {
+                match codec.read_frame(&mut reader).await {
+                    Ok(frame) => {
+                        if frames_tx.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(FrameError::Closed) => break,
+                    Err(e) => {
+                        warn!("Push session read failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        let mut heartbeat = tokio::time::interval_at(Instant::now() + self.heartbeat_interval, self.heartbeat_interval);
+        let mut last_frame = Instant::now();
+        loop {
+            tokio::select! {
+                frame = frames_rx.recv() => {
+                    let Some(frame) = frame else {
+                        warn!("Push session closed by server");
+                        break;
+                    };
+                    last_frame = Instant::now();
+                    let response: Response = match serde_json::from_slice(&frame) {
+                        Ok(response) => response,
+                        Err(e) => {
+                            warn!("Invalid frame in push session: {}", e);
+                            continue;
+                        }
+                    };
+                    self.check_unauthorized(&response);
+                    match response {
+                        Response::Push { messages } => {
+                            info!("Received {} pushed message(s)", messages.len());
+                            handler.on_messages(messages);
+                        }
+                        Response::Pong => {}
+                        Response::Unauthorized { message, .. } => {
+                            warn!("Push session refused by server: {}", message);
+                            self.status.record_error(format!("Unauthorized: {}", message));
+                            break;
+                        }
+                        response => warn!("Unexpected frame in push session: {:?}", response),
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_frame.elapsed() > self.heartbeat_interval * 3 {
+                        warn!("Push session timed out, no heartbeat for {:?}", last_frame.elapsed());
+                        break;
+                    }
+                    let ping = self.new_frame(Request::Ping);
+                    if let Err(e) = self.write_message(&mut writer, &serde_json::to_vec(&ping)?).await {
+                        warn!("Failed to send heartbeat: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+        reader_task.abort();
+        Ok(())
+    }
+    
+    pub async fn find_messages(&self) -> Result<Vec<Message>> {
+        debug!("Polling server for new messages");
+        let messages = match self.send_request(Request::Poll).await {
+            Ok(Response::Messages { messages }) => messages,
+            Ok(_) => {
+                self.status.record_poll(None);
+                return Err(anyhow::anyhow!("Unexpected reply to poll"));
+            }
+            Err(e) => {
+                self.status.record_poll(None);
+                return Err(e);
+            }
+        };
+        self.status.record_poll(Some(messages.len()));
+        if messages.is_empty() {
+            debug!("No new messages");
+        } else {
+            info!("Received {} new message(s)", messages.len());
+            for message in &messages {
+                debug!("Message: {:?}", message);
+            }
+        }
+        Ok(messages)
+    }
+
+    async fn send_request(&self, request: Request) -> Result<Response> {
+        let action = request.action();
+        let result = async {
+            let mut stream = self.connect().await?;
+            let frame = self.new_frame(request);
+            self.write_message(&mut stream, &serde_json::to_vec(&frame)?).await?;
+            let response_data = self.read_message(&mut stream).await?;
+            let response: Response = serde_json::from_slice(&response_data)?;
+            self.check_unauthorized(&response);
+            response.into_result()
+        }.await;
+        result.map_err(|e| {
+            warn!("Request {} failed: {}", action, e);
+            self.status.record_error(format!("Request {} failed: {}", action, e));
+            anyhow::anyhow!("Request {} failed: {}", action, e)
+        })
+    }
+
+    pub async fn ack(&self, ids: Vec<u64>) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        debug!("Acknowledging {} message(s)", ids.len());
+        self.send_request(Request::Ack { ids }).await?;
+        Ok(())
+    }
+
+    pub async fn report_applied(&self, id: u64) -> Result<()> {
+        debug!("Reporting message {} as applied", id);
+        self.send_request(Request::Applied { ids: vec![id] }).await?;
+        Ok(())
+    }
+
+    pub async fn report_failed(&self, id: u64, reason: String) -> Result<()> {
+        info!("Reporting message {} as failed: {}", id, reason);
+        self.send_request(Request::Failed { ids: vec![id], reason }).await?;
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,138 @@
+use anyhow::Result;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::sync::Arc;
+use tokio_rustls::TlsConnector;
+
+use gmod_tcp_shared::tls::{fingerprint_matches, parse_fingerprint};
+
+/// Accepts only the server certificate whose SHA-256 fingerprint is pinned in the config.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if fingerprint_matches(end_entity, &self.fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("server certificate does not match the pinned fingerprint".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+pub fn connector_for_fingerprint(fingerprint: &str) -> Result<TlsConnector> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinnedCertVerifier {
+        fingerprint: parse_fingerprint(fingerprint)?,
+        provider: Arc::clone(&provider),
+    };
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gmod_tcp_shared::tls::fingerprint;
+    use rustls::pki_types::PrivateKeyDer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsAcceptor;
+
+    const SERVER_NAME: &str = "gmod_tcp_server";
+
+    fn self_signed() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()]).unwrap();
+        let key = PrivateKeyDer::try_from(certified.signing_key.serialize_der()).unwrap();
+        (certified.cert.der().clone(), key)
+    }
+
+    /// Serves one TLS connection on loopback that echoes a byte, returns its address.
+    async fn serve_once(cert: CertificateDer<'static>, key: PrivateKeyDer<'static>) -> std::net::SocketAddr {
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            if let Ok(mut tls) = acceptor.accept(socket).await {
+                let mut byte = [0u8; 1];
+                if tls.read_exact(&mut byte).await.is_ok() {
+                    let _ = tls.write_all(&byte).await;
+                }
+            }
+        });
+        addr
+    }
+
+    async fn connect(addr: std::net::SocketAddr, pinned: &str) -> Result<()> {
+        let connector = connector_for_fingerprint(pinned)?;
+        let socket = TcpStream::connect(addr).await?;
+        let mut tls = connector.connect(ServerName::try_from(SERVER_NAME)?, socket).await?;
+        tls.write_all(b"x").await?;
+        let mut byte = [0u8; 1];
+        tls.read_exact(&mut byte).await?;
+        assert_eq!(&byte, b"x");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn accepts_pinned_certificate() {
+        let (cert, key) = self_signed();
+        let pinned = fingerprint(&cert);
+        let addr = serve_once(cert, key).await;
+        connect(addr, &pinned).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_other_certificate() {
+        let (cert, key) = self_signed();
+        let (other, _) = self_signed();
+        let addr = serve_once(cert, key).await;
+        let error = connect(addr, &fingerprint(&other)).await.unwrap_err();
+        assert!(error.to_string().contains("pinned fingerprint"), "{}", error);
+    }
+}
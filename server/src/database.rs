@@ -1,317 +1,199 @@
+use crate::storage::Storage;
 use crate::tcp::TcpServer;
 
 use anyhow::Result;
-use rusqlite::{Connection, params};
-use chrono::{DateTime, Utc};
-use gmod_tcp_shared::types::{Message, Donate, Player, ClientConnection};
-use serde_json;
+use chrono::Utc;
+use gmod_tcp_shared::types::{Message, Donate, ClientConnection, ClientState, PendingMessages, ServerInfo, UpdateClientRequest};
+use std::net::IpAddr;
+use std::sync::Arc;
 use tracing::info;
 
-const DB_PATH: &str = "data/server.db";
-
 impl TcpServer {
-    pub async fn init_database(&self) -> Result<()> {
-        std::fs::create_dir_all("data")?;
-        let db = Connection::open(DB_PATH)?;
-        db.execute("
-            CREATE TABLE IF NOT EXISTS clients (
-            uuid TEXT PRIMARY KEY,
-            server_name TEXT NOT NULL,
-            registered_at TEXT NOT NULL,
-            last_seen TEXT NOT NULL
-        );
-        ", [])?;
-        db.execute("
-            CREATE TABLE IF NOT EXISTS messages (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            client_uuid TEXT NOT NULL,
-            message_type TEXT NOT NULL,
-            message_data TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            delivered_at TEXT,
-            status TEXT NOT NULL DEFAULT 'pending',
-            FOREIGN KEY (client_uuid) REFERENCES clients(uuid)
-        );
-        ", [])?;
-        db.execute("
-            CREATE TABLE IF NOT EXISTS donates (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            message_id INTEGER,
-            client_uuid TEXT NOT NULL,
-            account_name TEXT DEFAULT NULL,
-            account_steam_id TEXT NOT NULL,
-            who_name TEXT NOT NULL,
-            who_steam_id TEXT DEFAULT NULL,
-            donate_type TEXT NOT NULL,
-            value TEXT NOT NULL,
-            faction TEXT NOT NULL,
-            date TEXT NOT NULL,
-            time TEXT NOT NULL,
-            created_at TEXT NOT NULL,
-            FOREIGN KEY (client_uuid) REFERENCES clients(uuid),
-            FOREIGN KEY (message_id) REFERENCES messages(id)
-        );
-        ", [])?;
-        Ok(())
-    }
-    pub async fn register_client(&self, client_uuid: String) -> Result<()> {
-        let client_uuid_clone = client_uuid.clone();
-        let result = tokio::task::spawn_blocking(move || -> Result<()> {
-            let db = Connection::open(DB_PATH)?;
-            db.execute("
-                INSERT INTO clients (uuid, server_name, registered_at, last_seen) VALUES (?, ?, ?, ?);
-            ", params![&client_uuid_clone, &client_uuid_clone, Utc::now().to_rfc3339(), Utc::now().to_rfc3339()])?;
-            Ok(())
-        }).await?;
-        if result.is_err() {
-            info!("Failed to register client. Maybe he already registered?");
+    /// Brings the database up to the latest schema. With `dry_run` only reports what would change.
+    pub async fn init_database(storage: &Arc<dyn Storage>, dry_run: bool) -> Result<()> {
+        let storage = Arc::clone(storage);
+        let report = tokio::task::spawn_blocking(move || storage.migrate(dry_run)).await??;
+        if report.applied.is_empty() {
+            info!("Database schema is up to date (version {})", report.from);
+        } else if dry_run {
+            info!("Dry run: {} migration(s) would upgrade the schema from version {}, nothing was changed", report.applied.len(), report.from);
+        } else {
+            info!("Database schema upgraded from version {} to {}", report.from, report.to);
         }
         Ok(())
     }
-    pub async fn proof_client(&self, client_uuid: String) -> Result<()> {
-        let client_uuid_clone = client_uuid.clone();
-        let result = tokio::task::spawn_blocking(move || -> Result<i32> {
-            let db = Connection::open(DB_PATH)?;
-            let count: i32 = db.query_row("SELECT COUNT(*) FROM clients WHERE uuid = ?", [client_uuid_clone], |row| row.get(0))?;
-            Ok(count)
-        }).await??;
-        if result == 0 {
-            return Err(anyhow::anyhow!("Client not found"));
-        }
-        Ok(())
+    /// Runs `f` against the storage on the blocking thread pool.
+    async fn with_storage<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn Storage) -> Result<T> + Send + 'static,
+    {
+        let storage = Arc::clone(self.storage());
+        tokio::task::spawn_blocking(move || f(storage.as_ref())).await?
+    }
+    pub async fn register_client(&self, client_uuid: String, secret: String, info: ServerInfo, state: ClientState) -> Result<()> {
+        self.with_storage(move |storage| storage.register_client(&client_uuid, &secret, &info, state)).await
+    }
+    pub async fn update_client_info(&self, client_uuid: String, info: ServerInfo) -> Result<()> {
+        self.with_storage(move |storage| storage.update_client_info(&client_uuid, &info)).await
+    }
+    pub async fn update_client(&self, client_uuid: String, update: UpdateClientRequest) -> Result<bool> {
+        self.with_storage(move |storage| storage.update_client(&client_uuid, &update)).await
+    }
+    pub async fn get_client_state(&self, client_uuid: String) -> Result<Option<ClientState>> {
+        self.with_storage(move |storage| storage.get_client_state(&client_uuid)).await
+    }
+    pub async fn set_client_state(&self, client_uuid: String, state: ClientState) -> Result<bool> {
+        self.with_storage(move |storage| storage.set_client_state(&client_uuid, state)).await
+    }
+    pub async fn revoke_client_secret(&self, client_uuid: String) -> Result<bool> {
+        self.with_storage(move |storage| storage.revoke_client_secret(&client_uuid)).await
+    }
+    pub async fn disable_client(&self, client_uuid: String, pending: PendingMessages) -> Result<Option<usize>> {
+        self.with_storage(move |storage| storage.disable_client(&client_uuid, &pending)).await
+    }
+    pub async fn delete_client(&self, client_uuid: String, pending: PendingMessages) -> Result<Option<usize>> {
+        self.with_storage(move |storage| storage.delete_client(&client_uuid, &pending)).await
+    }
+    pub async fn get_client_secret(&self, client_uuid: String) -> Result<Option<String>> {
+        self.with_storage(move |storage| storage.get_client_secret(&client_uuid)).await
+    }
+    pub async fn set_client_secret(&self, client_uuid: String, secret: String) -> Result<()> {
+        self.with_storage(move |storage| storage.set_client_secret(&client_uuid, &secret)).await
     }
-
     pub async fn update_last_seen(&self, client_uuid: String) -> Result<()> {
-        let client_uuid_clone = client_uuid.clone();
-        tokio::task::spawn_blocking(move || -> Result<()> {
-            let db = Connection::open(DB_PATH)?;
-            db.execute("UPDATE clients SET last_seen = ? WHERE uuid = ?", params![Utc::now().to_rfc3339(), client_uuid_clone])?;
-            Ok(())
-        }).await??;
-        Ok(())
+        self.with_storage(move |storage| storage.update_last_seen(&client_uuid)).await
     }
 
+    /// Stores a message, with its donates row for a donate, and wakes the client's session.
     pub async fn create_message(&self, message: Message) -> Result<u64> {
-        let message_clone: Message = message.clone();
-        let message_type_clone = message.message_type.clone();
-        let client_uuid_clone = message.client_uuid.clone();
-        let message_data_clone = message.message_data.clone();
-        let message_id = tokio::task::spawn_blocking(move || -> Result<u64> {
-            let db = Connection::open(DB_PATH)?;
-            db.execute("INSERT INTO messages (client_uuid, message_type, message_data, created_at, status) VALUES (?, ?, ?, ?, ?);", params![message_clone.client_uuid, message_clone.message_type, serde_json::to_string(&message_clone.message_data)?, message_clone.created_at.to_rfc3339(), message_clone.status])?;
-            let id = db.last_insert_rowid() as u64;
-            Ok(id)
-        }).await??;
-        if message_type_clone == "donate" {
-            let donate: Donate = serde_json::from_value(message_data_clone.clone())?;
-            let donate_id = self.create_donate(donate, message_id, client_uuid_clone.clone()).await?;
-            
-            let mut updated_message_data = message_data_clone;
-            if let Some(obj) = updated_message_data.as_object_mut() {
-                obj.insert("id".to_string(), serde_json::json!(donate_id));
-            }
-            
-            let message_id_clone = message_id;
-            tokio::task::spawn_blocking(move || -> Result<()> {
-                let db = Connection::open(DB_PATH)?;
-                db.execute(
-                    "UPDATE messages SET message_data = ? WHERE id = ?",
-                    params![serde_json::to_string(&updated_message_data)?, message_id_clone]
-                )?;
-                Ok(())
-            }).await??;
-        }
+        let client_uuid = message.client_uuid.clone();
+        let message_id = self.with_storage(move |storage| storage.create_message(&message)).await?;
+        self.notify_client(&client_uuid);
         Ok(message_id)
     }
-    pub async fn create_donate(&self, donate: Donate, message_id: u64, client_uuid: String) -> Result<u64> {
-        let donate_clone = donate.clone();
-        let client_uuid_clone = client_uuid.clone();
-        let donate_id = tokio::task::spawn_blocking(move || -> Result<u64> {
-            let db = Connection::open(DB_PATH)?;
-            db.execute("INSERT INTO donates (message_id, client_uuid, account_name, account_steam_id, who_name, who_steam_id, donate_type, value, faction, date, time, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);", params![message_id, client_uuid_clone, donate_clone.account.name, donate_clone.account.steam_id, donate_clone.who.name, donate_clone.who.steam_id, donate_clone.donate_type, donate_clone.value, donate_clone.faction, donate_clone.date.to_rfc3339(), donate_clone.time.to_rfc3339(), Utc::now().to_rfc3339()])?;
-            let id = db.last_insert_rowid() as u64;
-            Ok(id)
-        }).await??;
-        Ok(donate_id)
-    }
     pub async fn get_pending_messages(&self, client_uuid: String) -> Result<Vec<Message>> {
-        let client_uuid_clone = client_uuid.clone();
-        let messages = tokio::task::spawn_blocking(move || -> Result<Vec<Message>> {
-            let db = Connection::open(DB_PATH)?;
-            let mut stmt = db.prepare("SELECT id, client_uuid, message_type, message_data, created_at, delivered_at, status FROM messages WHERE client_uuid = ? AND status = 'pending'")?;
-            let messages: Result<Vec<Message>, _> = stmt.query_map([client_uuid_clone], |row| {
-                let created_at_str: String = row.get(4)?;
-                let delivered_at_str: Option<String> = row.get(5)?;
-                Ok(Message {
-                    id: row.get(0)?,
-                    client_uuid: row.get(1)?,
-                    message_type: row.get(2)?,
-                    message_data: serde_json::from_str(&row.get::<_, String>(3)?).map_err(|_| rusqlite::Error::InvalidColumnType(0, "message_data".to_string(), rusqlite::types::Type::Text))?,
-                    created_at: DateTime::parse_from_rfc3339(&created_at_str).map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                    delivered_at: delivered_at_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc))),
-                    status: row.get(6)?,
-                })
-            })?.collect();
-            messages.map_err(|e| anyhow::anyhow!("Database error: {}", e))
-        }).await??;
-        Ok(messages)
+        self.with_storage(move |storage| storage.get_pending_messages(&client_uuid)).await
     }
-    pub async fn mark_messages_delivered(&self, ids: Vec<u64>) -> Result<()> {
-        let ids_clone = ids.clone();
-        let now = Utc::now().to_rfc3339();
-        tokio::task::spawn_blocking(move || -> Result<()> {
-            let db = Connection::open(DB_PATH)?;
-            if ids_clone.is_empty() {
-                return Ok(());
-            }
-            for id in ids_clone {
-                db.execute("UPDATE messages SET status = 'delivered', delivered_at = ? WHERE id = ?", params![now, id])?;
-            }
-            Ok(())
-        }).await??;
-        Ok(())
+    pub async fn lease_messages(&self, ids: Vec<u64>) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.with_storage(move |storage| storage.lease_messages(&ids)).await
     }
-    pub async fn get_donates(&self) -> Result<Vec<Donate>> {
-        let donates = tokio::task::spawn_blocking(move || -> Result<Vec<Donate>> {
-            let db = Connection::open(DB_PATH)?;
-            let mut stmt = db.prepare("SELECT id, client_uuid, account_name, account_steam_id, date, faction, time, donate_type, value, who_name, who_steam_id FROM donates")?;
-            let donates: Result<Vec<Donate>, _> = stmt.query_map([], |row| {
-                let date_str: String = row.get(4)?;
-                let time_str: String = row.get(6)?;
-                Ok(Donate {
-                    id: Some(row.get(0)?),
-                    client_uuid: Some(row.get(1)?),
-                    account: Player {
-                        name: row.get(2)?,
-                        steam_id: row.get(3)?,
-                    },
-                    date: DateTime::parse_from_rfc3339(&date_str).map_err(|_| rusqlite::Error::InvalidColumnType(4, "date".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                    faction: row.get(5)?,
-                    time: DateTime::parse_from_rfc3339(&time_str).map_err(|_| rusqlite::Error::InvalidColumnType(6, "time".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                    donate_type: row.get(7)?,
-                    value: row.get(8)?,
-                    who: Player {
-                        name: row.get(9)?,
-                        steam_id: row.get(10)?,
-                    },
-                })
-            })?.collect();
-            donates.map_err(|e| anyhow::anyhow!("Database error: {}", e))
-        }).await??;
-        Ok(donates)
+    pub async fn mark_messages_delivered(&self, client_uuid: String, ids: Vec<u64>) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        self.with_storage(move |storage| storage.mark_messages_delivered(&client_uuid, &ids)).await
+    }
+    pub async fn mark_messages_applied(&self, client_uuid: String, ids: Vec<u64>) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        self.with_storage(move |storage| storage.mark_messages_applied(&client_uuid, &ids)).await
+    }
+    pub async fn mark_messages_failed(&self, client_uuid: String, ids: Vec<u64>, reason: String) -> Result<usize> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let retry = self.retry().clone();
+        self.with_storage(move |storage| storage.mark_messages_failed(&client_uuid, &ids, &reason, &retry)).await
+    }
+    pub async fn release_expired_leases(&self, lease_timeout: chrono::Duration) -> Result<usize> {
+        self.with_storage(move |storage| storage.release_expired_leases(lease_timeout)).await
     }
-    
-    pub async fn get_donate_by_id(&self, donate_id: u64) -> Result<Option<(Donate, String)>> {
-        let result = tokio::task::spawn_blocking(move || -> Result<Option<(Donate, String)>> {
-            let db = Connection::open(DB_PATH)?;
-            let mut stmt = db.prepare("SELECT id, account_name, account_steam_id, date, faction, time, donate_type, value, who_name, who_steam_id, client_uuid FROM donates WHERE id = ?")?;
-            match stmt.query_row(params![donate_id], |row| {
-                let date_str: String = row.get(3)?;
-                let time_str: String = row.get(5)?;
-                let client_uuid: String = row.get(10)?;
-                Ok((
-                    Donate {
-                        id: Some(row.get(0)?),
-                        client_uuid: Some(client_uuid.clone()),
-                        account: Player {
-                            name: row.get(1)?,
-                            steam_id: row.get(2)?,
-                        },
-                        date: DateTime::parse_from_rfc3339(&date_str).map_err(|_| rusqlite::Error::InvalidColumnType(3, "date".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                        faction: row.get(4)?,
-                        time: DateTime::parse_from_rfc3339(&time_str).map_err(|_| rusqlite::Error::InvalidColumnType(5, "time".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                        donate_type: row.get(6)?,
-                        value: row.get(7)?,
-                        who: Player {
-                            name: row.get(8)?,
-                            steam_id: row.get(9)?,
-                        },
-                    },
-                    client_uuid,
-                ))
-            }) {
-                Ok(val) => Ok(Some(val)),
-                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-                Err(e) => Err(anyhow::anyhow!("Database error: {}", e)),
-            }
-        }).await??;
-        Ok(result)
+    pub async fn get_donates(&self) -> Result<Vec<Donate>> {
+        self.with_storage(|storage| storage.get_donates()).await
     }
-    
+
+    /// Deletes a donate, queueing `donate_deleted` for its client, and wakes the client's session.
     pub async fn delete_donate(&self, donate_id: u64) -> Result<Option<(Donate, String)>> {
-        let donate_info = self.get_donate_by_id(donate_id).await?;
-        
-        if let Some((_, _)) = &donate_info {
-            tokio::task::spawn_blocking(move || -> Result<()> {
-                let db = Connection::open(DB_PATH)?;
-                db.execute("DELETE FROM donates WHERE id = ?", params![donate_id])?;
-                Ok(())
-            }).await??;
+        let deleted = self.with_storage(move |storage| storage.delete_donate(donate_id)).await?;
+        if let Some((_, client_uuid)) = &deleted {
+            self.notify_client(client_uuid);
         }
-        
-        Ok(donate_info)
+        Ok(deleted)
     }
-    
+
+    /// Updates a donate, queueing `donate_updated` for its client, and wakes the client's session.
     pub async fn update_donate(&self, donate_id: u64, donate: Donate) -> Result<Option<String>> {
-        let old_donate_info = self.get_donate_by_id(donate_id).await?;
-        let client_uuid = old_donate_info.as_ref().map(|(_, uuid)| uuid.clone());
-        
-        let donate_clone = donate.clone();
-        tokio::task::spawn_blocking(move || -> Result<()> {
-            let db = Connection::open(DB_PATH)?;
-            db.execute(
-                "UPDATE donates SET account_name = ?, account_steam_id = ?, who_name = ?, who_steam_id = ?, donate_type = ?, value = ?, faction = ?, date = ?, time = ? WHERE id = ?",
-                params![
-                    donate_clone.account.name,
-                    donate_clone.account.steam_id,
-                    donate_clone.who.name,
-                    donate_clone.who.steam_id,
-                    donate_clone.donate_type,
-                    donate_clone.value,
-                    donate_clone.faction,
-                    donate_clone.date.to_rfc3339(),
-                    donate_clone.time.to_rfc3339(),
-                    donate_id
-                ]
-            )?;
-            Ok(())
-        }).await??;
+        let client_uuid = self.with_storage(move |storage| storage.update_donate(donate_id, &donate)).await?;
+        if let Some(client_uuid) = &client_uuid {
+            self.notify_client(client_uuid);
+        }
         Ok(client_uuid)
     }
-    
+
     pub async fn get_clients(&self) -> Result<Vec<ClientConnection>> {
-        let clients = tokio::task::spawn_blocking(move || -> Result<Vec<ClientConnection>> {
-            let db = Connection::open(DB_PATH)?;
-            let mut stmt = db.prepare("SELECT uuid, server_name, registered_at, last_seen FROM clients")?;
-            let clients: Result<Vec<ClientConnection>, _> = stmt.query_map([], |row| {
-                let registered_at_str: String = row.get(2)?;
-                let last_seen_str: String = row.get(3)?;
-                Ok(ClientConnection {
-                    uuid: row.get(0)?,
-                    server_name: row.get(1)?,
-                    registered_at: DateTime::parse_from_rfc3339(&registered_at_str).map_err(|_| rusqlite::Error::InvalidColumnType(2, "registered_at".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                    last_seen: DateTime::parse_from_rfc3339(&last_seen_str).map_err(|_| rusqlite::Error::InvalidColumnType(3, "last_seen".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
-                })
-            })?.collect();
-            clients.map_err(|e| anyhow::anyhow!("Database error: {}", e))
-        }).await??;
-        Ok(clients)
+        self.with_storage(|storage| storage.get_clients()).await
+    }
+    /// Bans an address from the client listener, now and after a restart.
+    pub async fn ban_address(&self, ip: IpAddr) -> Result<bool> {
+        let stored = self.with_storage(move |storage| storage.ban_address(ip)).await?;
+        Ok(self.limits().ban(ip) || stored)
+    }
+    /// Lifts a ban, whether it was set through the API or `BANNED_IPS`. Addresses in
+    /// `BANNED_IPS` are banned again on the next start.
+    pub async fn unban_address(&self, ip: IpAddr) -> Result<bool> {
+        let stored = self.with_storage(move |storage| storage.unban_address(ip)).await?;
+        Ok(self.limits().unban(ip) || stored)
     }
-    pub async fn clear_delivered_messages(&self) -> Result<()> {
-        tokio::task::spawn_blocking(move || -> Result<()> {
-            let db = Connection::open(DB_PATH)?;
-            let cutoff_time = Utc::now().to_rfc3339();
+    /// Returns false when the client already signed a request with the nonce.
+    pub async fn record_nonce(&self, client_uuid: String, nonce: String, signed_at: i64, expired_before: i64) -> Result<bool> {
+        self.with_storage(move |storage| storage.record_nonce(&client_uuid, &nonce, signed_at, expired_before)).await
+    }
+    /// Prunes delivered and cancelled messages past the retention period, archiving them first when configured.
+    pub async fn prune_messages(&self) -> Result<usize> {
+        let retention = self.retention().clone();
+        let older_than = Utc::now() - retention.max_age;
+        self.with_storage(move |storage| storage.prune_messages(older_than, &mut |messages| retention.archive(messages))).await
+    }
+}
 
-            db.execute(
-                "DELETE FROM donates WHERE message_id IN (
-                    SELECT id FROM messages WHERE status = 'delivered' AND delivered_at < ?
-                )",
-                params![cutoff_time]
-            )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{donate_message, TempDb};
+    use crate::storage::{SqliteStorage, DEFAULT_DB_POOL_SIZE};
+    use std::time::Instant;
 
-            db.execute("DELETE FROM messages WHERE status = 'delivered' AND delivered_at < ?", params![cutoff_time])?;
-            Ok(())
-        }).await??;
-        Ok(())
+    /// Donates created concurrently, as by several managers at once through `POST /api/donates`.
+    /// Run with `cargo test --release -p gmod_tcp_server -- --ignored --nocapture create_message_throughput`,
+    /// `BENCH_POOL_SIZE` changes the pool size.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn create_message_throughput() {
+        const WRITERS: usize = 8;
+        const DONATES_PER_WRITER: usize = 250;
+        let pool_size = std::env::var("BENCH_POOL_SIZE").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_DB_POOL_SIZE);
+
+        let file = TempDb::new("bench");
+        let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::open(&file.0, pool_size).unwrap());
+        TcpServer::init_database(&storage, false).await.unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Arc::new(TcpServer::with_listener(storage, listener).await.unwrap());
+        server.register_client("bench".to_string(), "secret".to_string(), ServerInfo::default(), ClientState::Active).await.unwrap();
+
+        let started = Instant::now();
+        let writers: Vec<_> = (0..WRITERS).map(|writer| {
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                for n in 0..DONATES_PER_WRITER {
+                    server.create_message(donate_message("bench", writer * DONATES_PER_WRITER + n)).await.unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+        let elapsed = started.elapsed();
+
+        let total = WRITERS * DONATES_PER_WRITER;
+        assert_eq!(server.get_pending_messages("bench".to_string()).await.unwrap().len(), total);
+        println!(
+            "create_message: {} donates from {} writers with {} connection(s) in {:?}, {:.0}/s",
+            total, WRITERS, pool_size, elapsed, total as f64 / elapsed.as_secs_f64()
+        );
     }
-}
\ No newline at end of file
+}
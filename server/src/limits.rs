@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use gmod_tcp_shared::types::ConnectionStats;
+
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 120;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Banned,
+    RateLimited,
+    AtCapacity,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Banned => write!(f, "address is banned"),
+            Rejection::RateLimited => write!(f, "rate limit exceeded"),
+            Rejection::AtCapacity => write!(f, "too many concurrent connections"),
+        }
+    }
+}
+
+/// What a request is counted against for the rate limit: the client once its signature
+/// checked out, else the address it came from. Game servers behind one NAT so get a budget each.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateKey {
+    Client(String),
+    Address(IpAddr),
+}
+
+#[derive(Default)]
+struct Counters {
+    accepted: AtomicU64,
+    rejected_banned: AtomicU64,
+    rejected_rate_limited: AtomicU64,
+    rejected_at_capacity: AtomicU64,
+}
+
+/// Admission control for the client listener: a cap on open connections, a ban list and
+/// a rate limit on requests per client or address.
+pub struct ConnectionLimits {
+    connections: Arc<Semaphore>,
+    max_connections: usize,
+    rate_limit: u32,
+    windows: Mutex<HashMap<RateKey, (Instant, u32)>>,
+    banned: RwLock<HashSet<IpAddr>>,
+    counters: Counters,
+}
+
+impl ConnectionLimits {
+    pub fn new(max_connections: usize, rate_limit: u32, banned: HashSet<IpAddr>) -> Self {
+        Self {
+            connections: Arc::new(Semaphore::new(max_connections)),
+            max_connections,
+            rate_limit,
+            windows: Mutex::new(HashMap::new()),
+            banned: RwLock::new(banned),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Reads `MAX_CONNECTIONS`, `RATE_LIMIT_PER_MINUTE` and `BANNED_IPS` from `.env`.
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        let max_connections = std::env::var("MAX_CONNECTIONS")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        let rate_limit = std::env::var("RATE_LIMIT_PER_MINUTE")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_RATE_LIMIT_PER_MINUTE);
+        let banned = std::env::var("BANNED_IPS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| match s.parse::<IpAddr>() {
+                Ok(ip) => Some(ip),
+                Err(e) => {
+                    warn!("Ignoring invalid address {} in BANNED_IPS: {}", s, e);
+                    None
+                }
+            })
+            .collect::<HashSet<_>>();
+        info!(
+            "Accepting up to {} concurrent connections, {} requests per minute per client, {} banned address(es)",
+            max_connections, rate_limit, banned.len()
+        );
+        Self::new(max_connections, rate_limit, banned)
+    }
+
+    /// Admits a new connection from `ip`. The returned permit holds a connection slot until dropped.
+    /// Requests on it are counted separately, by `check_rate`, once the connection got a slot.
+    pub fn admit(&self, ip: IpAddr) -> Result<OwnedSemaphorePermit, Rejection> {
+        let result = if self.banned.read().unwrap().contains(&ip) {
+            Err(Rejection::Banned)
+        } else {
+            Arc::clone(&self.connections).try_acquire_owned().map_err(|_| Rejection::AtCapacity)
+        };
+        let counter = match result {
+            Ok(_) => &self.counters.accepted,
+            Err(Rejection::Banned) => &self.counters.rejected_banned,
+            Err(Rejection::RateLimited) => &self.counters.rejected_rate_limited,
+            Err(Rejection::AtCapacity) => &self.counters.rejected_at_capacity,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Counts a request against `key`, rejecting it once the key used up its budget for the minute.
+    pub fn check_rate(&self, key: RateKey) -> Result<(), Rejection> {
+        self.check_rate_at(key, Instant::now())
+    }
+
+    fn check_rate_at(&self, key: RateKey, now: Instant) -> Result<(), Rejection> {
+        let mut windows = self.windows.lock().unwrap();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+        let (_, count) = windows.entry(key).or_insert((now, 0));
+        if *count >= self.rate_limit {
+            self.counters.rejected_rate_limited.fetch_add(1, Ordering::Relaxed);
+            return Err(Rejection::RateLimited);
+        }
+        *count += 1;
+        Ok(())
+    }
+
+    pub fn ban(&self, ip: IpAddr) -> bool {
+        self.banned.write().unwrap().insert(ip)
+    }
+
+    pub fn unban(&self, ip: IpAddr) -> bool {
+        self.banned.write().unwrap().remove(&ip)
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let mut banned_ips = self.banned.read().unwrap()
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect::<Vec<_>>();
+        banned_ips.sort();
+        ConnectionStats {
+            active_connections: self.max_connections - self.connections.available_permits(),
+            max_connections: self.max_connections,
+            rate_limit_per_minute: self.rate_limit,
+            accepted: self.counters.accepted.load(Ordering::Relaxed),
+            rejected_banned: self.counters.rejected_banned.load(Ordering::Relaxed),
+            rejected_rate_limited: self.counters.rejected_rate_limited.load(Ordering::Relaxed),
+            rejected_at_capacity: self.counters.rejected_at_capacity.load(Ordering::Relaxed),
+            banned_ips,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    fn client(uuid: &str) -> RateKey {
+        RateKey::Client(uuid.to_string())
+    }
+
+    #[test]
+    fn rate_window_rolls_over() {
+        let limits = ConnectionLimits::new(8, 2, HashSet::new());
+        let start = Instant::now();
+        assert!(limits.check_rate_at(client("rp-1"), start).is_ok());
+        assert!(limits.check_rate_at(client("rp-1"), start + Duration::from_secs(1)).is_ok());
+        assert_eq!(limits.check_rate_at(client("rp-1"), start + Duration::from_secs(59)), Err(Rejection::RateLimited));
+        assert!(limits.check_rate_at(client("rp-1"), start + RATE_WINDOW).is_ok());
+        assert_eq!(limits.stats().rejected_rate_limited, 1);
+    }
+
+    #[test]
+    fn clients_behind_one_address_have_their_own_budget() {
+        let limits = ConnectionLimits::new(8, 1, HashSet::new());
+        let now = Instant::now();
+        assert!(limits.check_rate_at(client("rp-1"), now).is_ok());
+        assert!(limits.check_rate_at(client("rp-2"), now).is_ok());
+        assert!(limits.check_rate_at(RateKey::Address(CLIENT_IP), now).is_ok());
+        assert_eq!(limits.check_rate_at(client("rp-1"), now), Err(Rejection::RateLimited));
+        assert_eq!(limits.check_rate_at(RateKey::Address(CLIENT_IP), now), Err(Rejection::RateLimited));
+    }
+
+    #[test]
+    fn caps_concurrent_connections() {
+        let limits = ConnectionLimits::new(2, 1, HashSet::new());
+        let first = limits.admit(CLIENT_IP).unwrap();
+        let _second = limits.admit(CLIENT_IP).unwrap();
+        assert_eq!(limits.admit(CLIENT_IP).unwrap_err(), Rejection::AtCapacity);
+        assert_eq!(limits.stats().active_connections, 2);
+
+        // Rejected connections do not use up the rate budget, which only requests count against.
+        assert!(limits.check_rate(RateKey::Address(CLIENT_IP)).is_ok());
+        drop(first);
+        assert!(limits.admit(CLIENT_IP).is_ok());
+
+        let stats = limits.stats();
+        assert_eq!((stats.accepted, stats.rejected_at_capacity, stats.rejected_rate_limited), (3, 1, 0));
+    }
+
+    #[test]
+    fn rejects_banned_addresses() {
+        let other: IpAddr = "2001:db8::1".parse().unwrap();
+        let limits = ConnectionLimits::new(8, 8, HashSet::from([other]));
+        assert_eq!(limits.admit(other).unwrap_err(), Rejection::Banned);
+
+        assert!(limits.ban(CLIENT_IP));
+        assert!(!limits.ban(CLIENT_IP));
+        assert_eq!(limits.admit(CLIENT_IP).unwrap_err(), Rejection::Banned);
+        assert_eq!(limits.stats().banned_ips, vec!["10.0.0.1".to_string(), "2001:db8::1".to_string()]);
+
+        assert!(limits.unban(CLIENT_IP));
+        assert!(!limits.unban(CLIENT_IP));
+        assert!(limits.admit(CLIENT_IP).is_ok());
+        assert_eq!(limits.stats().rejected_banned, 2);
+    }
+}
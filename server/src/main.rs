@@ -2,6 +2,10 @@ mod tcp;
 mod database;
 mod rest;
 mod rest_handlers;
+mod tls;
+mod limits;
+mod retention;
+mod storage;
 
 use anyhow::Result;
 use std::sync::Arc;
@@ -14,10 +18,20 @@ use tracing::{info, error};
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     info!("Starting GMod TCP Server");
-    
-    let tcp_server = Arc::new(TcpServer::new().await?);
-    tcp_server.init_database().await?;
+
+    // --migrate-only upgrades the database and exits, --dry-run only reports pending migrations.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let migrate_only = dry_run || args.iter().any(|arg| arg == "--migrate-only");
+
+    let storage = storage::open()?;
+    TcpServer::init_database(&storage, dry_run).await?;
     info!("Database initialized");
+    if migrate_only {
+        return Ok(());
+    }
+
+    let tcp_server = Arc::new(TcpServer::new(storage).await?);
     
     let tcp_server_clone = tcp_server.clone();
     tokio::spawn(async move {
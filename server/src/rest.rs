@@ -1,5 +1,5 @@
 use axum:: {
-    Router, http::{Method, HeaderValue, StatusCode, Request}, routing::{get, post, delete, put}, middleware::Next, response::Response
+    Router, http::{Method, HeaderValue, StatusCode, Request}, routing::{get, post, delete, put, patch}, middleware::Next, response::Response
 };
 use anyhow::{Result, Context};
 use std::net::SocketAddr;
@@ -75,7 +75,7 @@ impl RestServer {
         let allowed_origins = std::env::var("ALLOWED_ORIGINS").unwrap_or_else(|_| "*".to_string());
         let cors = CorsLayer::new()
             .allow_origin(allowed_origins.parse::<HeaderValue>().unwrap())
-            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::PATCH])
             .allow_headers([axum::http::header::HeaderName::from_static("x-api-key")])
             .allow_credentials(false);
 
@@ -90,11 +90,19 @@ impl RestServer {
             .route("/", get(|| async { (StatusCode::NOT_FOUND, "Not Found") }))
             .route("/ping", get(|| async { "pong" }))
             .route("/api/clients", get(rest_handlers::get_clients))
+            .route("/api/clients/{client_uuid}", patch(rest_handlers::update_client))
+            .route("/api/clients/{client_uuid}", delete(rest_handlers::delete_client))
+            .route("/api/clients/{client_uuid}/enable", post(rest_handlers::enable_client))
+            .route("/api/clients/{client_uuid}/disable", post(rest_handlers::disable_client))
+            .route("/api/clients/{client_uuid}/revoke", post(rest_handlers::revoke_client))
             .route("/api/messages/{client_uuid}", get(rest_handlers::get_messages))
             .route("/api/donates", get(rest_handlers::get_donates))
             .route("/api/donates", post(rest_handlers::create_donate))
             .route("/api/donates/{donate_id}", delete(rest_handlers::delete_donate))
             .route("/api/donates/{donate_id}", put(rest_handlers::update_donate))
+            .route("/api/connections", get(rest_handlers::get_connection_stats))
+            .route("/api/bans/{ip}", put(rest_handlers::ban_ip))
+            .route("/api/bans/{ip}", delete(rest_handlers::unban_ip))
             .fallback(|| async { (StatusCode::NOT_FOUND, "Not Found") })
             .layer( 
                 TraceLayer::new_for_http()
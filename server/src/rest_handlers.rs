@@ -1,8 +1,9 @@
-use gmod_tcp_shared::types::{ClientConnection, Donate};
+use gmod_tcp_shared::types::{ClientConnection, ClientState, ConnectionStats, Donate, PendingMessages, UpdateClientRequest};
 use axum::{Json, extract::{Path, State}};
 use crate::tcp::TcpServer;
 use gmod_tcp_shared::types::{Message, CreateRequest, CreateResponse};
-use tracing::{info, error};
+use tracing::{info, warn, error};
+use std::net::IpAddr;
 use std::sync::Arc;
 use chrono::Utc;
 use axum::http::StatusCode;
@@ -23,6 +24,174 @@ pub async fn get_clients(State(server): State<Arc<TcpServer>>) -> Json<Vec<Clien
     Json(clients)
 }
 
+pub async fn update_client(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>, Json(update): Json<UpdateClientRequest>) -> Result<Json<CreateResponse>, StatusCode> {
+    if update.server_name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        warn!("PATCH /api/clients/{}: Rejected empty server name", client_uuid);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match server.update_client(client_uuid.clone(), update).await {
+        Ok(true) => {
+            info!("PATCH /api/clients/{}: Client updated", client_uuid);
+            Ok(Json(CreateResponse {
+                status: "ok".to_string(),
+                message: format!("Client {} updated", client_uuid),
+            }))
+        },
+        Ok(false) => {
+            error!("Client {} not found", client_uuid);
+            Err(StatusCode::NOT_FOUND)
+        },
+        Err(e) => {
+            error!("Error updating client {}: {}", client_uuid, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Rejects moving messages to the client they come from, to a client that does not exist, and
+/// to one that is disabled or waiting for approval, where they would sit undelivered.
+async fn check_reassign_target(server: &TcpServer, client_uuid: &str, pending: &PendingMessages) -> Result<(), StatusCode> {
+    let PendingMessages::Reassign { to } = pending else {
+        return Ok(());
+    };
+    if to == client_uuid {
+        warn!("Cannot reassign messages of client {} to itself", client_uuid);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match server.get_client_state(to.clone()).await {
+        Ok(Some(ClientState::Active)) => Ok(()),
+        Ok(Some(state)) => {
+            warn!("Cannot reassign messages of client {}: client {} is {}", client_uuid, to, state.as_str());
+            Err(StatusCode::CONFLICT)
+        },
+        Ok(None) => {
+            warn!("Cannot reassign messages of client {}: client {} not found", client_uuid, to);
+            Err(StatusCode::BAD_REQUEST)
+        },
+        Err(e) => {
+            error!("Error looking up client {}: {}", to, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Describes how the undelivered messages of a client were settled, waking the client they moved to.
+fn describe_settled(server: &TcpServer, pending: &PendingMessages, settled: usize) -> String {
+    match pending {
+        PendingMessages::Keep => "pending messages kept".to_string(),
+        PendingMessages::Cancel => format!("{} pending message(s) cancelled", settled),
+        PendingMessages::Reassign { to } => {
+            server.notify_client(to);
+            format!("{} pending message(s) reassigned to {}", settled, to)
+        },
+    }
+}
+
+async fn require_client(server: &TcpServer, client_uuid: &str) -> Result<ClientState, StatusCode> {
+    match server.get_client_state(client_uuid.to_string()).await {
+        Ok(Some(state)) => Ok(state),
+        Ok(None) => {
+            error!("Client {} not found", client_uuid);
+            Err(StatusCode::NOT_FOUND)
+        },
+        Err(e) => {
+            error!("Error looking up client {}: {}", client_uuid, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Enables a disabled client or approves one waiting for approval.
+pub async fn enable_client(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>) -> Result<Json<CreateResponse>, StatusCode> {
+    let previous = require_client(&server, &client_uuid).await?;
+    if let Err(e) = server.set_client_state(client_uuid.clone(), ClientState::Active).await {
+        error!("Error enabling client {}: {}", client_uuid, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    info!("POST /api/clients/{}/enable: Client enabled (was {})", client_uuid, previous.as_str());
+    Ok(Json(CreateResponse {
+        status: "ok".to_string(),
+        message: format!("Client {} enabled", client_uuid),
+    }))
+}
+
+/// Stops delivering to a client. Its pending messages are kept unless the body says otherwise.
+pub async fn disable_client(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>, pending: Option<Json<PendingMessages>>) -> Result<Json<CreateResponse>, StatusCode> {
+    let pending = pending.map(|Json(pending)| pending).unwrap_or(PendingMessages::Keep);
+    require_client(&server, &client_uuid).await?;
+    check_reassign_target(&server, &client_uuid, &pending).await?;
+    let settled = match server.disable_client(client_uuid.clone(), pending.clone()).await {
+        Ok(Some(settled)) => settled,
+        Ok(None) => {
+            error!("Client {} not found", client_uuid);
+            return Err(StatusCode::NOT_FOUND);
+        },
+        Err(e) => {
+            error!("Error disabling client {}: {}", client_uuid, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    server.notify_client(&client_uuid);
+    let settled = describe_settled(&server, &pending, settled);
+    warn!("POST /api/clients/{}/disable: Client disabled, {}", client_uuid, settled);
+    Ok(Json(CreateResponse {
+        status: "ok".to_string(),
+        message: format!("Client {} disabled, {}", client_uuid, settled),
+    }))
+}
+
+/// Drops the secret of a client. It gets a new one when it registers again, subject to approval if required.
+pub async fn revoke_client(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>) -> Result<Json<CreateResponse>, StatusCode> {
+    match server.revoke_client_secret(client_uuid.clone()).await {
+        Ok(true) => {
+            server.notify_client(&client_uuid);
+            warn!("POST /api/clients/{}/revoke: Client credentials revoked", client_uuid);
+            Ok(Json(CreateResponse {
+                status: "ok".to_string(),
+                message: format!("Credentials of client {} revoked", client_uuid),
+            }))
+        },
+        Ok(false) => {
+            error!("Client {} not found", client_uuid);
+            Err(StatusCode::NOT_FOUND)
+        },
+        Err(e) => {
+            error!("Error revoking credentials of client {}: {}", client_uuid, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Removes a client. Its pending messages are cancelled unless the body reassigns them.
+pub async fn delete_client(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>, pending: Option<Json<PendingMessages>>) -> Result<Json<CreateResponse>, StatusCode> {
+    let pending = pending.map(|Json(pending)| pending).unwrap_or(PendingMessages::Cancel);
+    if pending == PendingMessages::Keep {
+        // A new client registering under the same uuid would receive them.
+        warn!("DELETE /api/clients/{}: Pending messages must be cancelled or reassigned", client_uuid);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    require_client(&server, &client_uuid).await?;
+    check_reassign_target(&server, &client_uuid, &pending).await?;
+    let settled = match server.delete_client(client_uuid.clone(), pending.clone()).await {
+        Ok(Some(settled)) => settled,
+        Ok(None) => {
+            error!("Client {} not found", client_uuid);
+            return Err(StatusCode::NOT_FOUND);
+        },
+        Err(e) => {
+            error!("Error deleting client {}: {}", client_uuid, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    server.notify_client(&client_uuid);
+    let settled = describe_settled(&server, &pending, settled);
+    warn!("DELETE /api/clients/{}: Client deleted, {}", client_uuid, settled);
+    Ok(Json(CreateResponse {
+        status: "ok".to_string(),
+        message: format!("Client {} deleted, {}", client_uuid, settled),
+    }))
+}
+
 pub async fn get_messages(Path(client_uuid): Path<String>, State(server): State<Arc<TcpServer>>) -> Json<Vec<Message>> {
     let messages = match server.get_pending_messages(client_uuid).await {
         Ok(messages) => messages,
@@ -50,6 +219,24 @@ pub async fn get_donates(State(server): State<Arc<TcpServer>>) -> Json<Vec<Donat
 
 pub async fn create_donate(State(server): State<Arc<TcpServer>>, Json(request): Json<CreateRequest>) -> Json<CreateResponse> {
     info!("POST /api/donates: Received request for client {}", request.client_uuid);
+    match server.get_client_state(request.client_uuid.clone()).await {
+        Ok(Some(ClientState::Disabled)) => {
+            warn!("POST /api/donates: Client {} is disabled", request.client_uuid);
+            return Json(CreateResponse {
+                status: "error".to_string(),
+                message: format!("Client {} is disabled", request.client_uuid),
+            });
+        },
+        Ok(None) => {
+            warn!("POST /api/donates: Client {} not found", request.client_uuid);
+            return Json(CreateResponse {
+                status: "error".to_string(),
+                message: format!("Client {} not found", request.client_uuid),
+            });
+        },
+        Ok(_) => {},
+        Err(e) => error!("Error looking up client {}: {}", request.client_uuid, e),
+    }
     match server.create_message(Message{
         id: 0,
         client_uuid: request.client_uuid.clone(),
@@ -58,6 +245,8 @@ pub async fn create_donate(State(server): State<Arc<TcpServer>>, Json(request):
         created_at: Utc::now(),
         delivered_at: None,
         status: "pending".to_string(),
+        outcome: None,
+        failure_reason: None,
     }).await {
         Ok(message_id) => {
             info!("POST /api/donates: Created donate for client {} (message_id: {})", request.client_uuid, message_id);
@@ -78,22 +267,7 @@ pub async fn create_donate(State(server): State<Arc<TcpServer>>, Json(request):
 
 pub async fn delete_donate(Path(donate_id): Path<u64>, State(server): State<Arc<TcpServer>>) -> Result<Json<CreateResponse>, StatusCode> {
     match server.delete_donate(donate_id).await {
-        Ok(Some((donate, client_uuid))) => {
-            let message_data = serde_json::json!({
-                "donate_id": donate_id,
-                "donate": donate
-            });
-            if let Err(e) = server.create_message(Message {
-                id: 0,
-                client_uuid: client_uuid.clone(),
-                message_type: "donate_deleted".to_string(),
-                message_data,
-                created_at: Utc::now(),
-                delivered_at: None,
-                status: "pending".to_string(),
-            }).await {
-                error!("Error creating delete message for client {}: {}", client_uuid, e);
-            }
+        Ok(Some((_, client_uuid))) => {
             info!("DELETE /api/donates/{}: Donate deleted successfully, message sent to client {}", donate_id, client_uuid);
             Ok(Json(CreateResponse {
                 status: "ok".to_string(),
@@ -112,25 +286,8 @@ pub async fn delete_donate(Path(donate_id): Path<u64>, State(server): State<Arc<
 }
 
 pub async fn update_donate(Path(donate_id): Path<u64>, State(server): State<Arc<TcpServer>>, Json(donate): Json<Donate>) -> Result<Json<CreateResponse>, StatusCode> {
-    match server.update_donate(donate_id, donate.clone()).await {
+    match server.update_donate(donate_id, donate).await {
         Ok(Some(client_uuid)) => {
-            let mut updated_donate = donate.clone();
-            updated_donate.id = Some(donate_id);
-            let message_data = serde_json::json!({
-                "donate_id": donate_id,
-                "donate": updated_donate
-            });
-            if let Err(e) = server.create_message(Message {
-                id: 0,
-                client_uuid: client_uuid.clone(),
-                message_type: "donate_updated".to_string(),
-                message_data,
-                created_at: Utc::now(),
-                delivered_at: None,
-                status: "pending".to_string(),
-            }).await {
-                error!("Error creating update message for client {}: {}", client_uuid, e);
-            }
             info!("PUT /api/donates/{}: Donate updated successfully, message sent to client {}", donate_id, client_uuid);
             Ok(Json(CreateResponse {
                 status: "ok".to_string(),
@@ -146,4 +303,83 @@ pub async fn update_donate(Path(donate_id): Path<u64>, State(server): State<Arc<
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
-}
\ No newline at end of file
+}
+
+pub async fn get_connection_stats(State(server): State<Arc<TcpServer>>) -> Json<ConnectionStats> {
+    Json(server.limits().stats())
+}
+
+pub async fn ban_ip(Path(ip): Path<IpAddr>, State(server): State<Arc<TcpServer>>) -> Result<Json<CreateResponse>, StatusCode> {
+    let message = match server.ban_address(ip).await {
+        Ok(true) => {
+            warn!("PUT /api/bans/{}: Address banned", ip);
+            format!("Address {} banned", ip)
+        }
+        Ok(false) => format!("Address {} is already banned", ip),
+        Err(e) => {
+            error!("Error banning address {}: {}", ip, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    Ok(Json(CreateResponse {
+        status: "ok".to_string(),
+        message,
+    }))
+}
+
+pub async fn unban_ip(Path(ip): Path<IpAddr>, State(server): State<Arc<TcpServer>>) -> Result<Json<CreateResponse>, StatusCode> {
+    match server.unban_address(ip).await {
+        Ok(true) => {}
+        Ok(false) => {
+            error!("Address {} is not banned", ip);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Err(e) => {
+            error!("Error unbanning address {}: {}", ip, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    info!("DELETE /api/bans/{}: Address unbanned", ip);
+    Ok(Json(CreateResponse {
+        status: "ok".to_string(),
+        message: format!("Address {} unbanned", ip),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{donate_message, TempDb};
+    use crate::storage::{SqliteStorage, Storage, DEFAULT_DB_POOL_SIZE};
+    use gmod_tcp_shared::types::ServerInfo;
+
+    async fn pending_ids(server: &TcpServer, client_uuid: &str) -> Vec<u64> {
+        server.get_pending_messages(client_uuid.to_string()).await.unwrap().iter().map(|message| message.id).collect()
+    }
+
+    #[tokio::test]
+    async fn reassigns_only_to_active_clients() {
+        let file = TempDb::new("reassign");
+        let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::open(&file.0, DEFAULT_DB_POOL_SIZE).unwrap());
+        TcpServer::init_database(&storage, false).await.unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Arc::new(TcpServer::with_listener(storage, listener).await.unwrap());
+        for (client_uuid, state) in [("rp-1", ClientState::Active), ("rp-2", ClientState::Disabled), ("rp-3", ClientState::Pending)] {
+            server.register_client(client_uuid.to_string(), "secret".to_string(), ServerInfo::default(), state).await.unwrap();
+        }
+        let message_id = server.create_message(donate_message("rp-1", 1)).await.unwrap();
+
+        for target in ["rp-2", "rp-3"] {
+            let pending = PendingMessages::Reassign { to: target.to_string() };
+            let result = disable_client(Path("rp-1".to_string()), State(Arc::clone(&server)), Some(Json(pending))).await;
+            assert_eq!(result.err(), Some(StatusCode::CONFLICT), "{}", target);
+        }
+        assert_eq!(server.get_client_state("rp-1".to_string()).await.unwrap(), Some(ClientState::Active));
+        assert_eq!(pending_ids(&server, "rp-1").await, vec![message_id]);
+
+        server.set_client_state("rp-2".to_string(), ClientState::Active).await.unwrap();
+        let pending = PendingMessages::Reassign { to: "rp-2".to_string() };
+        assert!(disable_client(Path("rp-1".to_string()), State(Arc::clone(&server)), Some(Json(pending))).await.is_ok());
+        assert_eq!(pending_ids(&server, "rp-2").await, vec![message_id]);
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use gmod_tcp_shared::types::Message;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use tracing::info;
+
+const DEFAULT_MESSAGE_RETENTION_DAYS: u32 = 30;
+
+/// How long delivered and cancelled messages are kept. Donates are the ledger and are never pruned,
+/// they only lose the link to their message.
+#[derive(Clone)]
+pub struct RetentionPolicy {
+    pub max_age: chrono::Duration,
+    /// When set, pruned messages are appended here first, one JSON object per line.
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl RetentionPolicy {
+    /// Reads `MESSAGE_RETENTION_DAYS` and `MESSAGE_ARCHIVE_DIR` from `.env`.
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        let days = std::env::var("MESSAGE_RETENTION_DAYS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_MESSAGE_RETENTION_DAYS);
+        let archive_dir = std::env::var("MESSAGE_ARCHIVE_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+        info!("Delivered and cancelled messages are kept for {} day(s)", days);
+        if let Some(dir) = &archive_dir {
+            info!("Pruned messages are archived to {}", dir.display());
+        }
+        Self { max_age: chrono::Duration::days(days.into()), archive_dir }
+    }
+
+    /// Appends `messages` to today's file in the archive directory and syncs it,
+    /// so they are on disk before the transaction deleting them commits.
+    pub fn archive(&self, messages: &[Message]) -> Result<()> {
+        let Some(dir) = &self.archive_dir else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("messages-{}.jsonl", Utc::now().format("%Y-%m-%d")));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open message archive {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        for message in messages {
+            serde_json::to_writer(&mut writer, message)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u64) -> Message {
+        Message {
+            id,
+            client_uuid: "rp-1".to_string(),
+            message_type: "donate".to_string(),
+            message_data: serde_json::json!({ "id": id }),
+            created_at: Utc::now(),
+            delivered_at: Some(Utc::now()),
+            status: "delivered".to_string(),
+            outcome: None,
+            failure_reason: None,
+        }
+    }
+
+    #[test]
+    fn appends_messages_as_json_lines() {
+        let dir = std::env::temp_dir().join(format!("gmod_tcp_archive_{}", std::process::id()));
+        let policy = RetentionPolicy { max_age: chrono::Duration::zero(), archive_dir: Some(dir.clone()) };
+        policy.archive(&[message(1), message(2)]).unwrap();
+        policy.archive(&[message(3)]).unwrap();
+
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let ids: Vec<u64> = std::fs::read_to_string(&file).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Message>(line).unwrap().id)
+            .collect();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+}
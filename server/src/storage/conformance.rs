@@ -0,0 +1,427 @@
+//! Behaviour every `Storage` backend has to share. Backends run `run` from their own tests,
+//! handing out a freshly migrated, empty storage for each check.
+
+use super::{RetryPolicy, Storage, FAIL_AT};
+
+use chrono::Utc;
+use gmod_tcp_shared::types::{ClientState, Donate, Message, PendingMessages, Player, ServerInfo, UpdateClientRequest};
+use std::path::PathBuf;
+
+/// A database file in the temp dir, removed with its WAL files on drop.
+pub struct TempDb(pub PathBuf);
+
+impl TempDb {
+    pub fn new(name: &str) -> Self {
+        TempDb(std::env::temp_dir().join(format!("gmod_tcp_{}_{}.db", name, std::process::id())))
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.0.display(), suffix));
+        }
+    }
+}
+
+type Check = fn(&dyn Storage);
+
+const CHECKS: &[(&str, Check)] = &[
+    ("registers_clients", registers_clients),
+    ("keeps_name_set_by_manager", keeps_name_set_by_manager),
+    ("moves_clients_between_states", moves_clients_between_states),
+    ("creates_donate_with_its_message", creates_donate_with_its_message),
+    ("failed_donate_creation_leaves_nothing", failed_donate_creation_leaves_nothing),
+    ("donate_for_unknown_client_is_rejected", donate_for_unknown_client_is_rejected),
+    ("leases_and_acknowledges_messages", leases_and_acknowledges_messages),
+    ("settles_pending_messages", settles_pending_messages),
+    ("failed_retirement_keeps_client_and_messages", failed_retirement_keeps_client_and_messages),
+    ("ignores_late_outcomes", ignores_late_outcomes),
+    ("delays_and_gives_up_failed_messages", delays_and_gives_up_failed_messages),
+    ("deletes_donate_and_notifies_client", deletes_donate_and_notifies_client),
+    ("failed_delete_keeps_donate", failed_delete_keeps_donate),
+    ("updates_donate_and_notifies_client", updates_donate_and_notifies_client),
+    ("failed_update_keeps_old_donate", failed_update_keeps_old_donate),
+    ("prunes_finished_messages_and_keeps_donates", prunes_finished_messages_and_keeps_donates),
+    ("failed_archive_prunes_nothing", failed_archive_prunes_nothing),
+    ("persists_bans", persists_bans),
+    ("rejects_replayed_nonces", rejects_replayed_nonces),
+    ("migrates_once", migrates_once),
+];
+
+/// Runs every check against the storage `fresh` returns for it. The second value of the
+/// pair is dropped after the check, to clean up what the storage left behind.
+pub fn run<S: Storage, G>(mut fresh: impl FnMut(&str) -> (S, G)) {
+    for (name, check) in CHECKS {
+        let (storage, _guard) = fresh(name);
+        println!("conformance: {}", name);
+        check(&storage);
+    }
+}
+
+pub fn donate(n: usize) -> Donate {
+    Donate {
+        id: None,
+        client_uuid: None,
+        account: Player { name: format!("Player {}", n), steam_id: "STEAM_0:1:156722227".to_string() },
+        date: Utc::now(),
+        faction: "all".to_string(),
+        time: Utc::now(),
+        donate_type: "weapon".to_string(),
+        value: "yufu_spear".to_string(),
+        who: Player { name: "Anerson Darling".to_string(), steam_id: "STEAM_0:1:1".to_string() },
+    }
+}
+
+pub fn donate_message(client_uuid: &str, n: usize) -> Message {
+    Message {
+        id: 0,
+        client_uuid: client_uuid.to_string(),
+        message_type: "donate".to_string(),
+        message_data: serde_json::to_value(donate(n)).unwrap(),
+        created_at: Utc::now(),
+        delivered_at: None,
+        status: "pending".to_string(),
+        outcome: None,
+        failure_reason: None,
+    }
+}
+
+/// Hands a failed message out again right away, so checks need not wait for a retry.
+const RETRY_AT_ONCE: RetryPolicy = RetryPolicy { base_delay: chrono::Duration::zero(), max_attempts: 5 };
+
+fn register(storage: &dyn Storage, client_uuid: &str) {
+    storage.register_client(client_uuid, "secret", &ServerInfo::default(), ClientState::Active).unwrap();
+}
+
+fn failing_at<T>(step: &'static str, f: impl FnOnce() -> T) -> T {
+    FAIL_AT.with(|fail_at| fail_at.set(Some(step)));
+    let result = f();
+    FAIL_AT.with(|fail_at| fail_at.set(None));
+    result
+}
+
+fn pending_ids(storage: &dyn Storage, client_uuid: &str) -> Vec<u64> {
+    storage.get_pending_messages(client_uuid).unwrap().iter().map(|message| message.id).collect()
+}
+
+fn registers_clients(storage: &dyn Storage) {
+    let info = ServerInfo { server_name: Some(" RP #1 ".to_string()), map: Some("gm_construct".to_string()), ..Default::default() };
+    storage.register_client("rp-1", "secret", &info, ClientState::Pending).unwrap();
+    register(storage, "rp-2");
+
+    let mut clients = storage.get_clients().unwrap();
+    clients.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+    assert_eq!(clients.len(), 2);
+    assert_eq!(clients[0].server_name, "RP #1");
+    assert_eq!(clients[0].map.as_deref(), Some("gm_construct"));
+    assert_eq!(clients[0].state, ClientState::Pending);
+    assert_eq!(clients[1].server_name, "rp-2");
+    assert_eq!(storage.get_client_secret("rp-1").unwrap().as_deref(), Some("secret"));
+    assert_eq!(storage.get_client_secret("ghost").unwrap(), None);
+
+    storage.set_client_secret("rp-1", "rotated").unwrap();
+    assert_eq!(storage.get_client_secret("rp-1").unwrap().as_deref(), Some("rotated"));
+    storage.update_last_seen("rp-1").unwrap();
+}
+
+fn keeps_name_set_by_manager(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let update = UpdateClientRequest { server_name: Some(" Main ".to_string()), notes: Some(" EU ".to_string()) };
+    assert!(storage.update_client("rp-1", &update).unwrap());
+    assert!(!storage.update_client("ghost", &update).unwrap());
+
+    let info = ServerInfo { server_name: Some("Reported".to_string()), hostname: Some("host".to_string()), ..Default::default() };
+    storage.update_client_info("rp-1", &info).unwrap();
+    let client = storage.get_clients().unwrap().remove(0);
+    assert_eq!(client.server_name, "Main");
+    assert_eq!(client.hostname.as_deref(), Some("host"));
+    assert_eq!(client.notes.as_deref(), Some("EU"));
+
+    let clear_notes = UpdateClientRequest { server_name: None, notes: Some("  ".to_string()) };
+    storage.update_client("rp-1", &clear_notes).unwrap();
+    assert_eq!(storage.get_clients().unwrap()[0].notes, None);
+
+    // Registering again starts over, with the reported name.
+    storage.register_client("rp-1", "secret", &info, ClientState::Active).unwrap();
+    assert_eq!(storage.get_clients().unwrap()[0].server_name, "Reported");
+}
+
+fn moves_clients_between_states(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    assert_eq!(storage.get_client_state("rp-1").unwrap(), Some(ClientState::Active));
+    assert_eq!(storage.get_client_state("ghost").unwrap(), None);
+
+    assert!(storage.set_client_state("rp-1", ClientState::Disabled).unwrap());
+    assert_eq!(storage.get_client_state("rp-1").unwrap(), Some(ClientState::Disabled));
+    assert!(!storage.set_client_state("ghost", ClientState::Active).unwrap());
+
+    assert!(storage.revoke_client_secret("rp-1").unwrap());
+    assert_eq!(storage.get_client_secret("rp-1").unwrap(), None);
+
+    assert_eq!(storage.delete_client("rp-1", &PendingMessages::Cancel).unwrap(), Some(0));
+    assert_eq!(storage.delete_client("rp-1", &PendingMessages::Cancel).unwrap(), None);
+    assert_eq!(storage.get_client_state("rp-1").unwrap(), None);
+    assert!(!storage.set_client_state("rp-1", ClientState::Active).unwrap());
+    assert!(storage.get_clients().unwrap().is_empty());
+
+    register(storage, "rp-1");
+    assert_eq!(storage.get_client_state("rp-1").unwrap(), Some(ClientState::Active));
+}
+
+fn creates_donate_with_its_message(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let message_id = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+
+    let donates = storage.get_donates().unwrap();
+    assert_eq!(donates.len(), 1);
+    assert_eq!(donates[0].client_uuid.as_deref(), Some("rp-1"));
+    assert_eq!(donates[0].account.name, "Player 1");
+    let messages = storage.get_pending_messages("rp-1").unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, message_id);
+    assert_eq!(messages[0].message_data["id"], serde_json::json!(donates[0].id.unwrap()));
+}
+
+fn failed_donate_creation_leaves_nothing(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    for step in ["message_inserted", "donate_inserted"] {
+        assert!(failing_at(step, || storage.create_message(&donate_message("rp-1", 1))).is_err());
+        assert!(storage.get_pending_messages("rp-1").unwrap().is_empty(), "{}", step);
+        assert!(storage.get_donates().unwrap().is_empty(), "{}", step);
+    }
+}
+
+fn donate_for_unknown_client_is_rejected(storage: &dyn Storage) {
+    assert!(storage.create_message(&donate_message("ghost", 1)).is_err());
+    assert!(storage.get_pending_messages("ghost").unwrap().is_empty());
+    assert!(storage.get_donates().unwrap().is_empty());
+}
+
+fn leases_and_acknowledges_messages(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let ids: Vec<u64> = (0..3).map(|n| storage.create_message(&donate_message("rp-1", n)).unwrap()).collect();
+    assert_eq!(pending_ids(storage, "rp-1"), ids);
+
+    // Only leased messages are acknowledged as delivered.
+    assert_eq!(storage.mark_messages_delivered("rp-1", &ids[..1]).unwrap(), 0);
+    storage.lease_messages(&ids).unwrap();
+    assert!(pending_ids(storage, "rp-1").is_empty());
+    assert_eq!(storage.mark_messages_delivered("other", &ids[..1]).unwrap(), 0);
+    assert_eq!(storage.mark_messages_delivered("rp-1", &ids[..1]).unwrap(), 1);
+
+    assert_eq!(storage.mark_messages_applied("rp-1", &ids[1..2]).unwrap(), 1);
+    assert_eq!(storage.mark_messages_failed("rp-1", &ids[..1], "no such player", &RETRY_AT_ONCE).unwrap(), 1);
+    let failed = storage.get_pending_messages("rp-1").unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].id, ids[0]);
+    assert_eq!(failed[0].outcome.as_deref(), Some("failed"));
+    assert_eq!(failed[0].failure_reason.as_deref(), Some("no such player"));
+
+    assert_eq!(storage.release_expired_leases(chrono::Duration::minutes(5)).unwrap(), 0);
+    assert_eq!(storage.release_expired_leases(chrono::Duration::zero()).unwrap(), 1);
+    assert_eq!(pending_ids(storage, "rp-1"), vec![ids[0], ids[2]]);
+}
+
+fn ignores_late_outcomes(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let applied = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let retried = storage.create_message(&donate_message("rp-1", 2)).unwrap();
+    let cancelled = storage.create_message(&donate_message("rp-1", 3)).unwrap();
+
+    // Pending messages were never handed out, there is nothing to acknowledge.
+    assert_eq!(storage.mark_messages_applied("rp-1", &[applied]).unwrap(), 0);
+    assert_eq!(storage.mark_messages_failed("rp-1", &[applied], "offline", &RETRY_AT_ONCE).unwrap(), 0);
+
+    storage.lease_messages(&[applied, retried]).unwrap();
+    assert_eq!(storage.mark_messages_applied("rp-1", &[applied]).unwrap(), 1);
+    assert_eq!(storage.mark_messages_failed("rp-1", &[applied], "replayed", &RETRY_AT_ONCE).unwrap(), 0);
+
+    // A failed message can fail again on its retry.
+    assert_eq!(storage.mark_messages_failed("rp-1", &[retried], "offline", &RETRY_AT_ONCE).unwrap(), 1);
+    storage.lease_messages(&[retried]).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-1", &[retried], "still offline", &RETRY_AT_ONCE).unwrap(), 1);
+
+    storage.disable_client("rp-1", &PendingMessages::Cancel).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-1", &[cancelled, retried], "late", &RETRY_AT_ONCE).unwrap(), 0);
+    assert_eq!(storage.mark_messages_applied("rp-1", &[cancelled]).unwrap(), 0);
+    assert!(pending_ids(storage, "rp-1").is_empty());
+}
+
+fn settles_pending_messages(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    register(storage, "rp-2");
+    let first = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let second = storage.create_message(&donate_message("rp-1", 2)).unwrap();
+    storage.lease_messages(&[second]).unwrap();
+
+    assert_eq!(storage.disable_client("rp-1", &PendingMessages::Keep).unwrap(), Some(0));
+    assert_eq!(pending_ids(storage, "rp-1"), vec![first]);
+    let reassign = PendingMessages::Reassign { to: "rp-2".to_string() };
+    assert_eq!(storage.disable_client("rp-1", &reassign).unwrap(), Some(2));
+    assert_eq!(pending_ids(storage, "rp-2"), vec![first, second]);
+    assert!(storage.get_donates().unwrap().iter().all(|donate| donate.client_uuid.as_deref() == Some("rp-2")));
+
+    assert_eq!(storage.delete_client("rp-2", &PendingMessages::Cancel).unwrap(), Some(2));
+    assert!(pending_ids(storage, "rp-2").is_empty());
+    storage.lease_messages(&[first]).unwrap();
+    assert_eq!(storage.release_expired_leases(chrono::Duration::zero()).unwrap(), 0);
+    assert_eq!(storage.disable_client("ghost", &PendingMessages::Cancel).unwrap(), None);
+}
+
+fn failed_retirement_keeps_client_and_messages(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let message_id = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    assert!(failing_at("client_retired", || storage.disable_client("rp-1", &PendingMessages::Cancel)).is_err());
+    assert!(failing_at("client_retired", || storage.delete_client("rp-1", &PendingMessages::Cancel)).is_err());
+    assert_eq!(storage.get_client_state("rp-1").unwrap(), Some(ClientState::Active));
+    assert_eq!(storage.get_client_secret("rp-1").unwrap().as_deref(), Some("secret"));
+    assert_eq!(pending_ids(storage, "rp-1"), vec![message_id]);
+}
+
+fn delays_and_gives_up_failed_messages(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    let delayed = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let retry = RetryPolicy { base_delay: chrono::Duration::hours(1), max_attempts: 5 };
+    storage.lease_messages(&[delayed]).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-1", &[delayed], "offline", &retry).unwrap(), 1);
+    // Not handed out again before its retry is due.
+    assert!(pending_ids(storage, "rp-1").is_empty());
+    assert_eq!(storage.release_expired_leases(chrono::Duration::zero()).unwrap(), 0);
+    assert!(pending_ids(storage, "rp-1").is_empty());
+
+    // Reassigning gives the message a fresh start on the new client.
+    register(storage, "rp-2");
+    storage.disable_client("rp-1", &PendingMessages::Reassign { to: "rp-2".to_string() }).unwrap();
+    assert_eq!(pending_ids(storage, "rp-2"), vec![delayed]);
+
+    let given_up = storage.create_message(&donate_message("rp-2", 2)).unwrap();
+    let retry = RetryPolicy { base_delay: chrono::Duration::zero(), max_attempts: 2 };
+    storage.lease_messages(&[given_up]).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-2", &[given_up], "no such player", &retry).unwrap(), 1);
+    assert_eq!(pending_ids(storage, "rp-2"), vec![delayed, given_up]);
+    storage.lease_messages(&[given_up]).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-2", &[given_up], "no such player", &retry).unwrap(), 1);
+    // The last attempt cancels the message, it is not retried or leased anymore.
+    assert_eq!(pending_ids(storage, "rp-2"), vec![delayed]);
+    storage.lease_messages(&[given_up]).unwrap();
+    assert_eq!(storage.mark_messages_failed("rp-2", &[given_up], "late", &retry).unwrap(), 0);
+}
+
+fn deletes_donate_and_notifies_client(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let donate_id = storage.get_donates().unwrap()[0].id.unwrap();
+
+    let (donate, client_uuid) = storage.delete_donate(donate_id).unwrap().unwrap();
+    assert_eq!(client_uuid, "rp-1");
+    assert_eq!(donate.account.name, "Player 1");
+    assert!(storage.get_donates().unwrap().is_empty());
+    let messages = storage.get_pending_messages("rp-1").unwrap();
+    assert_eq!(messages.last().unwrap().message_type, "donate_deleted");
+    assert_eq!(messages.last().unwrap().message_data["donate_id"], serde_json::json!(donate_id));
+    assert!(storage.delete_donate(donate_id).unwrap().is_none());
+}
+
+fn failed_delete_keeps_donate(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let donate_id = storage.get_donates().unwrap()[0].id.unwrap();
+
+    assert!(failing_at("donate_deleted", || storage.delete_donate(donate_id)).is_err());
+    assert_eq!(storage.get_donates().unwrap().len(), 1);
+    assert_eq!(storage.get_pending_messages("rp-1").unwrap().len(), 1);
+}
+
+fn updates_donate_and_notifies_client(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let donate_id = storage.get_donates().unwrap()[0].id.unwrap();
+
+    assert_eq!(storage.update_donate(donate_id, &donate(2)).unwrap().as_deref(), Some("rp-1"));
+    assert_eq!(storage.get_donates().unwrap()[0].account.name, "Player 2");
+    let notification = storage.get_pending_messages("rp-1").unwrap().pop().unwrap();
+    assert_eq!(notification.message_type, "donate_updated");
+    assert_eq!(notification.message_data["donate"]["id"], serde_json::json!(donate_id));
+    assert!(storage.update_donate(donate_id + 1, &donate(3)).unwrap().is_none());
+}
+
+fn failed_update_keeps_old_donate(storage: &dyn Storage) {
+    register(storage, "rp-1");
+    storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let donate_id = storage.get_donates().unwrap()[0].id.unwrap();
+
+    assert!(failing_at("donate_updated", || storage.update_donate(donate_id, &donate(2))).is_err());
+    assert_eq!(storage.get_donates().unwrap()[0].account.name, "Player 1");
+    assert_eq!(storage.get_pending_messages("rp-1").unwrap().len(), 1);
+}
+
+/// A delivered, a cancelled and a pending donate message of `rp-1`, by message id.
+fn finished_and_pending(storage: &dyn Storage) -> (u64, u64, u64) {
+    register(storage, "rp-1");
+    let delivered = storage.create_message(&donate_message("rp-1", 1)).unwrap();
+    let cancelled = storage.create_message(&donate_message("rp-1", 2)).unwrap();
+    storage.lease_messages(&[delivered]).unwrap();
+    storage.mark_messages_delivered("rp-1", &[delivered]).unwrap();
+    storage.disable_client("rp-1", &PendingMessages::Cancel).unwrap();
+    let pending = storage.create_message(&donate_message("rp-1", 3)).unwrap();
+    (delivered, cancelled, pending)
+}
+
+fn prunes_finished_messages_and_keeps_donates(storage: &dyn Storage) {
+    let (delivered, cancelled, pending) = finished_and_pending(storage);
+
+    let mut archived = Vec::new();
+    let past = Utc::now() - chrono::Duration::days(1);
+    assert_eq!(storage.prune_messages(past, &mut |messages| { archived.extend_from_slice(messages); Ok(()) }).unwrap(), 0);
+    assert!(archived.is_empty());
+
+    let future = Utc::now() + chrono::Duration::seconds(1);
+    assert_eq!(storage.prune_messages(future, &mut |messages| { archived.extend_from_slice(messages); Ok(()) }).unwrap(), 2);
+    let archived: Vec<(u64, &str)> = archived.iter().map(|message| (message.id, message.status.as_str())).collect();
+    assert_eq!(archived, vec![(delivered, "delivered"), (cancelled, "cancelled")]);
+    assert_eq!(pending_ids(storage, "rp-1"), vec![pending]);
+
+    let donates = storage.get_donates().unwrap();
+    assert_eq!(donates.len(), 3);
+    assert_eq!(storage.update_donate(donates[0].id.unwrap(), &donate(4)).unwrap().as_deref(), Some("rp-1"));
+    assert_eq!(storage.prune_messages(future, &mut |_| Ok(())).unwrap(), 0);
+}
+
+fn failed_archive_prunes_nothing(storage: &dyn Storage) {
+    finished_and_pending(storage);
+    let future = Utc::now() + chrono::Duration::seconds(1);
+
+    assert!(storage.prune_messages(future, &mut |_| Err(anyhow::anyhow!("disk full"))).is_err());
+    assert_eq!(storage.prune_messages(future, &mut |_| Ok(())).unwrap(), 2);
+}
+
+fn persists_bans(storage: &dyn Storage) {
+    let v4: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+    let v6: std::net::IpAddr = "2001:db8::1".parse().unwrap();
+    assert!(storage.get_banned_addresses().unwrap().is_empty());
+    assert!(storage.ban_address(v6).unwrap());
+    assert!(storage.ban_address(v4).unwrap());
+    assert!(!storage.ban_address(v4).unwrap());
+    assert_eq!(storage.get_banned_addresses().unwrap(), vec![v4, v6]);
+    assert!(storage.unban_address(v4).unwrap());
+    assert!(!storage.unban_address(v4).unwrap());
+    assert_eq!(storage.get_banned_addresses().unwrap(), vec![v6]);
+}
+
+fn rejects_replayed_nonces(storage: &dyn Storage) {
+    assert!(storage.record_nonce("client-a", "nonce-1", 1_000, 0).unwrap());
+    assert!(!storage.record_nonce("client-a", "nonce-1", 1_000, 0).unwrap());
+    assert!(storage.record_nonce("client-b", "nonce-1", 1_000, 0).unwrap());
+    // Nonces signed before the cutoff are forgotten, and may not be fresh anymore anyway.
+    assert!(storage.record_nonce("client-a", "nonce-2", 2_000, 1_500).unwrap());
+    assert!(storage.record_nonce("client-a", "nonce-1", 2_000, 1_500).unwrap());
+    assert!(!storage.record_nonce("client-a", "nonce-2", 2_000, 1_500).unwrap());
+}
+
+fn migrates_once(storage: &dyn Storage) {
+    let report = storage.migrate(false).unwrap();
+    assert!(report.applied.is_empty());
+    assert_eq!(report.from, report.to);
+    assert!(report.to > 0);
+}
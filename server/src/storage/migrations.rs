@@ -0,0 +1,261 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{Connection, Transaction, params};
+use tracing::info;
+
+/// One step of the schema, applied once in order of `version`.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    up: fn(&Transaction) -> Result<()>,
+}
+
+/// Every schema change, oldest first. Append new steps, never edit applied ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "baseline", up: baseline },
+    Migration { version: 2, name: "message_leases", up: message_leases },
+    Migration { version: 3, name: "client_secrets", up: client_secrets },
+    Migration { version: 4, name: "client_info", up: client_info },
+    Migration { version: 5, name: "client_state", up: client_state },
+    Migration { version: 6, name: "banned_addresses", up: banned_addresses },
+    Migration { version: 7, name: "request_nonces", up: request_nonces },
+    Migration { version: 8, name: "message_retries", up: message_retries },
+];
+
+fn baseline(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(include_str!("migrations/0001_baseline.sql"))?;
+    Ok(())
+}
+
+fn message_leases(tx: &Transaction) -> Result<()> {
+    add_column(tx, "messages", "leased_at", "TEXT")?;
+    add_column(tx, "messages", "outcome", "TEXT")?;
+    add_column(tx, "messages", "failure_reason", "TEXT")
+}
+
+fn client_secrets(tx: &Transaction) -> Result<()> {
+    add_column(tx, "clients", "secret", "TEXT")
+}
+
+fn client_info(tx: &Transaction) -> Result<()> {
+    add_column(tx, "clients", "map", "TEXT")?;
+    add_column(tx, "clients", "hostname", "TEXT")?;
+    add_column(tx, "clients", "notes", "TEXT")?;
+    // Set once a manager renames the client, the name it reports on registration no longer applies then.
+    add_column(tx, "clients", "name_overridden", "INTEGER NOT NULL DEFAULT 0")
+}
+
+fn client_state(tx: &Transaction) -> Result<()> {
+    add_column(tx, "clients", "state", "TEXT NOT NULL DEFAULT 'active'")
+}
+
+fn banned_addresses(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("
+        CREATE TABLE IF NOT EXISTS banned_addresses (
+            address TEXT PRIMARY KEY,
+            banned_at TEXT NOT NULL
+        );
+    ")?;
+    Ok(())
+}
+
+fn request_nonces(tx: &Transaction) -> Result<()> {
+    tx.execute_batch("
+        CREATE TABLE IF NOT EXISTS request_nonces (
+            client_uuid TEXT NOT NULL,
+            nonce TEXT NOT NULL,
+            signed_at INTEGER NOT NULL,
+            PRIMARY KEY (client_uuid, nonce)
+        );
+        CREATE INDEX IF NOT EXISTS idx_request_nonces_signed_at ON request_nonces(signed_at);
+    ")?;
+    Ok(())
+}
+
+fn message_retries(tx: &Transaction) -> Result<()> {
+    add_column(tx, "messages", "attempts", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(tx, "messages", "retry_at", "TEXT")
+}
+
+/// Databases written before versioning added columns on startup, so they may already have some.
+fn add_column(tx: &Transaction, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+    if !exists {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+/// Highest applied version, 0 for a new database or one from before versioning.
+pub fn current_version(db: &Connection) -> Result<u32> {
+    let has_table: bool = db.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(0);
+    }
+    let version: Option<u32> = db.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Applies the missing migrations in one transaction and returns them. With `dry_run`
+/// they are still executed, to catch errors, but rolled back.
+pub fn migrate(db: &mut Connection, dry_run: bool) -> Result<Vec<&'static Migration>> {
+    let current = current_version(db)?;
+    if let Some(latest) = MIGRATIONS.last() {
+        if current > latest.version {
+            return Err(anyhow::anyhow!(
+                "Database schema version {} is newer than this server ({}), refusing to start",
+                current, latest.version
+            ));
+        }
+    }
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|migration| migration.version > current).collect();
+    if pending.is_empty() {
+        return Ok(pending);
+    }
+
+    let tx = db.transaction()?;
+    tx.execute("
+        CREATE TABLE IF NOT EXISTS schema_version (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at TEXT NOT NULL
+    );
+    ", [])?;
+    for migration in &pending {
+        info!("{} migration {} ({})", if dry_run { "Checking" } else { "Applying" }, migration.version, migration.name);
+        (migration.up)(&tx).with_context(|| format!("Migration {} ({}) failed", migration.version, migration.name))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)",
+            params![migration.version, migration.name, Utc::now().to_rfc3339()],
+        )?;
+    }
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASELINE_FIXTURE: &str = include_str!("../../tests/fixtures/baseline.sql");
+
+    fn columns(db: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = db.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        stmt.query_map([], |row| row.get(1)).unwrap().map(|name| name.unwrap()).collect()
+    }
+
+    fn latest() -> u32 {
+        MIGRATIONS.last().unwrap().version
+    }
+
+    #[test]
+    fn versions_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as u32 + 1, "{}", migration.name);
+        }
+    }
+
+    #[test]
+    fn creates_new_database() {
+        let mut db = Connection::open_in_memory().unwrap();
+        let applied = migrate(&mut db, false).unwrap();
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&db).unwrap(), latest());
+        for column in ["secret", "map", "hostname", "notes", "name_overridden", "state"] {
+            assert!(columns(&db, "clients").contains(&column.to_string()), "clients.{}", column);
+        }
+        for column in ["leased_at", "outcome", "failure_reason"] {
+            assert!(columns(&db, "messages").contains(&column.to_string()), "messages.{}", column);
+        }
+    }
+
+    #[test]
+    fn upgrades_baseline_database() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(BASELINE_FIXTURE).unwrap();
+        assert_eq!(current_version(&db).unwrap(), 0);
+
+        migrate(&mut db, false).unwrap();
+        assert_eq!(current_version(&db).unwrap(), latest());
+
+        let (server_name, secret, state, name_overridden): (String, Option<String>, String, i64) = db.query_row(
+            "SELECT server_name, secret, state, name_overridden FROM clients WHERE uuid = 'rp-1'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        ).unwrap();
+        assert_eq!(server_name, "rp-1");
+        assert_eq!(secret, None);
+        assert_eq!(state, "active");
+        assert_eq!(name_overridden, 0);
+
+        let statuses: Vec<(String, Option<String>)> = db.prepare("SELECT status, outcome FROM messages ORDER BY id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(statuses, vec![("pending".to_string(), None), ("delivered".to_string(), None)]);
+        let donates: i64 = db.query_row("SELECT COUNT(*) FROM donates", [], |row| row.get(0)).unwrap();
+        assert_eq!(donates, 2);
+    }
+
+    #[test]
+    fn upgrades_database_with_columns_added_before_versioning() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(BASELINE_FIXTURE).unwrap();
+        db.execute_batch("
+            ALTER TABLE messages ADD COLUMN leased_at TEXT;
+            ALTER TABLE messages ADD COLUMN outcome TEXT;
+            ALTER TABLE messages ADD COLUMN failure_reason TEXT;
+            ALTER TABLE clients ADD COLUMN secret TEXT;
+            UPDATE clients SET secret = 'kept';
+        ").unwrap();
+
+        migrate(&mut db, false).unwrap();
+        assert_eq!(current_version(&db).unwrap(), latest());
+        let secret: String = db.query_row("SELECT secret FROM clients WHERE uuid = 'rp-1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(secret, "kept");
+    }
+
+    #[test]
+    fn applies_each_migration_once() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut db, false).unwrap();
+        assert!(migrate(&mut db, false).unwrap().is_empty());
+        let recorded: i64 = db.query_row("SELECT COUNT(*) FROM schema_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(recorded, MIGRATIONS.len() as i64);
+    }
+
+    #[test]
+    fn dry_run_leaves_database_untouched() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(BASELINE_FIXTURE).unwrap();
+        let before = columns(&db, "clients");
+
+        let pending = migrate(&mut db, true).unwrap();
+        assert_eq!(pending.len(), MIGRATIONS.len());
+        assert_eq!(current_version(&db).unwrap(), 0);
+        assert_eq!(columns(&db, "clients"), before);
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let mut db = Connection::open_in_memory().unwrap();
+        migrate(&mut db, false).unwrap();
+        db.execute(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'future', '')",
+            params![latest() + 1],
+        ).unwrap();
+        assert!(migrate(&mut db, false).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+CREATE TABLE IF NOT EXISTS clients (
+    uuid TEXT PRIMARY KEY,
+    server_name TEXT NOT NULL,
+    registered_at TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_uuid TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    message_data TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    delivered_at TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    FOREIGN KEY (client_uuid) REFERENCES clients(uuid)
+);
+
+CREATE TABLE IF NOT EXISTS donates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER,
+    client_uuid TEXT NOT NULL,
+    account_name TEXT DEFAULT NULL,
+    account_steam_id TEXT NOT NULL,
+    who_name TEXT NOT NULL,
+    who_steam_id TEXT DEFAULT NULL,
+    donate_type TEXT NOT NULL,
+    value TEXT NOT NULL,
+    faction TEXT NOT NULL,
+    date TEXT NOT NULL,
+    time TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (client_uuid) REFERENCES clients(uuid),
+    FOREIGN KEY (message_id) REFERENCES messages(id)
+);
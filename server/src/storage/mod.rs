@@ -0,0 +1,234 @@
+mod migrations;
+mod sqlite;
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(test)]
+pub(crate) mod conformance;
+
+pub use self::sqlite::SqliteStorage;
+#[cfg(feature = "postgres")]
+pub use self::postgres::PostgresStorage;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use gmod_tcp_shared::types::{ClientConnection, ClientState, Donate, Message, PendingMessages, ServerInfo, UpdateClientRequest};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const DEFAULT_DB_PATH: &str = "data/server.db";
+pub(crate) const DEFAULT_DB_POOL_SIZE: u32 = 4;
+const DEFAULT_MESSAGE_RETRY_SECS: i64 = 30;
+const DEFAULT_MESSAGE_MAX_ATTEMPTS: u32 = 5;
+
+/// What `Storage::migrate` found and did.
+pub struct MigrationReport {
+    /// Schema version before migrating.
+    pub from: u32,
+    /// Schema version after migrating, `from` again after a dry run.
+    pub to: u32,
+    /// Migrations that were applied, or would be with `dry_run`.
+    pub applied: Vec<&'static str>,
+}
+
+/// When a message a client failed to apply is handed out again.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Delay after the first failure, doubled after each further one.
+    pub base_delay: chrono::Duration,
+    /// Failures after which the message is cancelled instead of retried.
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// Reads `MESSAGE_RETRY_SECS` and `MESSAGE_MAX_ATTEMPTS` from `.env`.
+    pub fn from_env() -> Self {
+        dotenvy::dotenv().ok();
+        let retry_secs = std::env::var("MESSAGE_RETRY_SECS")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|secs| *secs >= 0)
+            .unwrap_or(DEFAULT_MESSAGE_RETRY_SECS);
+        let max_attempts = std::env::var("MESSAGE_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .filter(|attempts| *attempts > 0)
+            .unwrap_or(DEFAULT_MESSAGE_MAX_ATTEMPTS);
+        Self { base_delay: chrono::Duration::seconds(retry_secs), max_attempts }
+    }
+
+    /// When a message that failed `attempts` times is retried, `None` once it is given up on.
+    fn retry_at(&self, attempts: u32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        Some(now + self.base_delay * 2i32.pow(attempts.saturating_sub(1).min(16)))
+    }
+}
+
+/// Clients, their messages and the donate ledger. Calls block, the server makes them
+/// from the blocking thread pool. Every backend has to pass the suite in `conformance`.
+pub trait Storage: Send + Sync {
+    /// Brings the schema up to date. With `dry_run` the migrations run but are rolled back.
+    fn migrate(&self, dry_run: bool) -> Result<MigrationReport>;
+
+    /// Adds a client, or starts over with a deleted one registering under the same uuid.
+    fn register_client(&self, client_uuid: &str, secret: &str, info: &ServerInfo, state: ClientState) -> Result<()>;
+    /// Records what an already registered client reports about itself, keeping a name set by a manager.
+    fn update_client_info(&self, client_uuid: &str, info: &ServerInfo) -> Result<()>;
+    /// Renames or annotates a client. Returns false when there is no such client.
+    fn update_client(&self, client_uuid: &str, update: &UpdateClientRequest) -> Result<bool>;
+    /// Clients that are not deleted.
+    fn get_clients(&self) -> Result<Vec<ClientConnection>>;
+    /// State of a client, `None` when it is unknown or deleted.
+    fn get_client_state(&self, client_uuid: &str) -> Result<Option<ClientState>>;
+    /// Moves a client that is not deleted to `state`. Returns false when there is no such client.
+    fn set_client_state(&self, client_uuid: &str, state: ClientState) -> Result<bool>;
+    fn get_client_secret(&self, client_uuid: &str) -> Result<Option<String>>;
+    fn set_client_secret(&self, client_uuid: &str, secret: &str) -> Result<()>;
+    /// Forgets the secret of a client, so its requests are rejected until it registers again.
+    fn revoke_client_secret(&self, client_uuid: &str) -> Result<bool>;
+    /// Disables a client and cancels or reassigns its pending and leased messages in one transaction.
+    /// Returns how many messages were settled, `None` when there is no such client.
+    fn disable_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>>;
+    /// Hides a client, drops its secret and settles its messages like `disable_client`, in one
+    /// transaction. The row stays so donate history keeps pointing at it.
+    fn delete_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>>;
+    fn update_last_seen(&self, client_uuid: &str) -> Result<()>;
+
+    /// Stores a message, with its donates row for a donate, in one transaction.
+    fn create_message(&self, message: &Message) -> Result<u64>;
+    /// Pending messages of a client that are not waiting for a retry, oldest first.
+    fn get_pending_messages(&self, client_uuid: &str) -> Result<Vec<Message>>;
+    fn lease_messages(&self, ids: &[u64]) -> Result<()>;
+    fn mark_messages_delivered(&self, client_uuid: &str, ids: &[u64]) -> Result<usize>;
+    /// Records that leased or delivered messages were applied in game.
+    fn mark_messages_applied(&self, client_uuid: &str, ids: &[u64]) -> Result<usize>;
+    /// Returns leased or delivered messages to pending, held back until their retry is due, or
+    /// cancels them after `retry.max_attempts` failures. Cancelled and applied messages are
+    /// left alone, so a late or replayed nack cannot grant a donate twice.
+    fn mark_messages_failed(&self, client_uuid: &str, ids: &[u64], reason: &str, retry: &RetryPolicy) -> Result<usize>;
+    /// Returns messages leased longer than `lease_timeout` ago to pending.
+    fn release_expired_leases(&self, lease_timeout: chrono::Duration) -> Result<usize>;
+    /// Deletes delivered and cancelled messages finished before `older_than` and returns how many.
+    /// They are handed to `archive` first, in the same transaction, so a failed export prunes
+    /// nothing. Their donates stay, without the link to the message.
+    fn prune_messages(&self, older_than: DateTime<Utc>, archive: &mut dyn FnMut(&[Message]) -> Result<()>) -> Result<usize>;
+
+    /// Every donate, oldest first.
+    fn get_donates(&self) -> Result<Vec<Donate>>;
+    /// Deletes a donate and queues `donate_deleted` for its client in one transaction.
+    /// Returns the deleted donate and its client, `None` when there is no such donate.
+    fn delete_donate(&self, donate_id: u64) -> Result<Option<(Donate, String)>>;
+    /// Updates a donate and queues `donate_updated` for its client in one transaction.
+    /// Returns the client, `None` when there is no such donate.
+    fn update_donate(&self, donate_id: u64, donate: &Donate) -> Result<Option<String>>;
+
+    /// Addresses banned from the client listener through the REST API.
+    fn get_banned_addresses(&self) -> Result<Vec<IpAddr>>;
+    /// Returns false when the address was already banned.
+    fn ban_address(&self, ip: IpAddr) -> Result<bool>;
+    /// Returns false when the address was not banned.
+    fn unban_address(&self, ip: IpAddr) -> Result<bool>;
+
+    /// Records the nonce of a signed request and forgets those signed before `expired_before`.
+    /// Returns false when the client already used the nonce, so the request is a replay.
+    fn record_nonce(&self, client_uuid: &str, nonce: &str, signed_at: i64, expired_before: i64) -> Result<bool>;
+}
+
+/// Opens the storage `DATABASE_URL` points at when it is a `postgres://` URL,
+/// else the SQLite file at `DB_PATH`. Both pools hold up to `DB_POOL_SIZE` connections.
+pub fn open() -> Result<Arc<dyn Storage>> {
+    dotenvy::dotenv().ok();
+    let pool_size = std::env::var("DB_POOL_SIZE")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_DB_POOL_SIZE);
+    match std::env::var("DATABASE_URL").ok().filter(|url| !url.trim().is_empty()) {
+        Some(url) if url.starts_with("postgres://") || url.starts_with("postgresql://") => open_postgres(&url, pool_size),
+        Some(_) => Err(anyhow::anyhow!("DATABASE_URL has to be a postgres:// URL, use DB_PATH for SQLite")),
+        None => {
+            let path = std::env::var("DB_PATH").unwrap_or_else(|_| DEFAULT_DB_PATH.to_string());
+            Ok(Arc::new(SqliteStorage::open(path.as_ref(), pool_size)?))
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str, pool_size: u32) -> Result<Arc<dyn Storage>> {
+    Ok(Arc::new(PostgresStorage::open(url, pool_size)?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_url: &str, _pool_size: u32) -> Result<Arc<dyn Storage>> {
+    Err(anyhow::anyhow!("DATABASE_URL points at PostgreSQL, but the server was built without the postgres feature"))
+}
+
+/// The name a client is listed under: the one it reports, else its hostname, else its uuid.
+fn display_name(client_uuid: &str, info: &ServerInfo) -> String {
+    info.server_name.as_deref()
+        .or(info.hostname.as_deref())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or(client_uuid)
+        .to_string()
+}
+
+/// A pending message telling a client that one of its donates changed.
+fn donate_notification(client_uuid: &str, message_type: &str, donate_id: u64, donate: &Donate) -> Message {
+    Message {
+        id: 0,
+        client_uuid: client_uuid.to_string(),
+        message_type: message_type.to_string(),
+        message_data: serde_json::json!({
+            "donate_id": donate_id,
+            "donate": donate
+        }),
+        created_at: Utc::now(),
+        delivered_at: None,
+        status: "pending".to_string(),
+        outcome: None,
+        failure_reason: None,
+    }
+}
+
+/// The payload of a donate message once its donates row exists, carrying the row id.
+fn with_donate_id(message_data: &serde_json::Value, donate_id: u64) -> serde_json::Value {
+    let mut message_data = message_data.clone();
+    if let Some(obj) = message_data.as_object_mut() {
+        obj.insert("id".to_string(), serde_json::json!(donate_id));
+    }
+    message_data
+}
+
+// Lets tests abort a write between two of its steps, to check nothing of it is left behind.
+#[cfg(test)]
+thread_local! {
+    static FAIL_AT: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+#[cfg(test)]
+fn fail_point(step: &'static str) -> Result<()> {
+    if FAIL_AT.with(|fail_at| fail_at.get()) == Some(step) {
+        return Err(anyhow::anyhow!("Injected failure after {}", step));
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+fn fail_point(_step: &'static str) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_until_given_up() {
+        let retry = RetryPolicy { base_delay: chrono::Duration::seconds(30), max_attempts: 4 };
+        let now = Utc::now();
+        let delays: Vec<_> = (1..=4).map(|attempts| retry.retry_at(attempts, now).map(|at| (at - now).num_seconds())).collect();
+        assert_eq!(delays, vec![Some(30), Some(60), Some(120), None]);
+    }
+}
@@ -0,0 +1,597 @@
+use super::{display_name, donate_notification, fail_point, with_donate_id, MigrationReport, RetryPolicy, Storage};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use gmod_tcp_shared::types::{ClientConnection, ClientState, Donate, Message, PendingMessages, Player, ServerInfo, UpdateClientRequest};
+use postgres::{NoTls, Row, Transaction};
+use r2d2::{Pool, PooledConnection};
+use std::net::IpAddr;
+use r2d2_postgres::PostgresConnectionManager;
+use tracing::info;
+
+/// Every schema change, oldest first. Append new steps, never edit applied ones.
+/// The versions are those of this backend, they need not match the SQLite ones.
+const MIGRATIONS: &[(u32, &str, &str)] = &[
+    (1, "baseline", include_str!("postgres/0001_baseline.sql")),
+    (2, "banned_addresses", include_str!("postgres/0002_banned_addresses.sql")),
+    (3, "request_nonces", include_str!("postgres/0003_request_nonces.sql")),
+    (4, "message_retries", include_str!("postgres/0004_message_retries.sql")),
+];
+
+/// Held while migrating, so servers sharing the database do not migrate it at the same time.
+const MIGRATION_LOCK: i64 = 0x676d_6f64_5f74_6370;
+
+/// A PostgreSQL database several servers can share.
+pub struct PostgresStorage {
+    /// Only taken when dropping.
+    pool: Option<Pool<PostgresConnectionManager<NoTls>>>,
+}
+
+impl PostgresStorage {
+    pub fn open(url: &str, pool_size: u32) -> Result<Self> {
+        let config: postgres::Config = url.parse().context("Invalid DATABASE_URL")?;
+        let manager = PostgresConnectionManager::new(config.clone(), NoTls);
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .build(manager)
+            .context("Failed to connect to PostgreSQL")?;
+        info!(
+            "PostgreSQL database {} on {:?} opened with up to {} connection(s)",
+            config.get_dbname().unwrap_or_default(), config.get_hosts(), pool_size
+        );
+        Ok(PostgresStorage { pool: Some(pool) })
+    }
+
+    fn db(&self) -> Result<PooledConnection<PostgresConnectionManager<NoTls>>> {
+        let pool = self.pool.as_ref().ok_or_else(|| anyhow::anyhow!("Storage is closed"))?;
+        Ok(pool.get()?)
+    }
+
+    /// Moves a client out of service with `update` and settles its messages in the same transaction.
+    fn retire_client(&self, update: &str, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        if tx.execute(update, &[&client_uuid])? == 0 {
+            return Ok(None);
+        }
+        fail_point("client_retired")?;
+        let settled = match pending {
+            PendingMessages::Keep => 0,
+            PendingMessages::Cancel => tx.execute(
+                "UPDATE messages SET status = 'cancelled', leased_at = NULL WHERE client_uuid = $1 AND status IN ('pending', 'leased')",
+                &[&client_uuid],
+            )?,
+            PendingMessages::Reassign { to } => {
+                tx.execute(
+                    "UPDATE donates SET client_uuid = $1 WHERE message_id IN (
+                        SELECT id FROM messages WHERE client_uuid = $2 AND status IN ('pending', 'leased')
+                    )",
+                    &[to, &client_uuid],
+                )?;
+                tx.execute(
+                    "UPDATE messages SET client_uuid = $1, status = 'pending', leased_at = NULL, attempts = 0, retry_at = NULL WHERE client_uuid = $2 AND status IN ('pending', 'leased')",
+                    &[to, &client_uuid],
+                )?
+            }
+        };
+        tx.commit()?;
+        Ok(Some(settled as usize))
+    }
+}
+
+impl Drop for PostgresStorage {
+    // A connection closes by blocking on a runtime of its own, which panics on a tokio
+    // thread, so the pool is dropped on a plain one.
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            let _ = std::thread::spawn(move || drop(pool)).join();
+        }
+    }
+}
+
+fn current_version(tx: &mut Transaction) -> Result<u32> {
+    let has_table: bool = tx.query_one("SELECT to_regclass('schema_version') IS NOT NULL", &[])?.try_get(0)?;
+    if !has_table {
+        return Ok(0);
+    }
+    let version: Option<i32> = tx.query_one("SELECT MAX(version) FROM schema_version", &[])?.try_get(0)?;
+    Ok(version.unwrap_or(0) as u32)
+}
+
+fn ids(ids: &[u64]) -> Vec<i64> {
+    ids.iter().map(|id| *id as i64).collect()
+}
+
+fn message_from_row(row: &Row) -> Result<Message> {
+    Ok(Message {
+        id: row.try_get::<_, i64>("id")? as u64,
+        client_uuid: row.try_get("client_uuid")?,
+        message_type: row.try_get("message_type")?,
+        message_data: row.try_get("message_data")?,
+        created_at: row.try_get("created_at")?,
+        delivered_at: row.try_get("delivered_at")?,
+        status: row.try_get("status")?,
+        outcome: row.try_get("outcome")?,
+        failure_reason: row.try_get("failure_reason")?,
+    })
+}
+
+fn donate_from_row(row: &Row) -> Result<Donate> {
+    Ok(Donate {
+        id: Some(row.try_get::<_, i64>("id")? as u64),
+        client_uuid: Some(row.try_get("client_uuid")?),
+        account: Player {
+            name: row.try_get("account_name")?,
+            steam_id: row.try_get("account_steam_id")?,
+        },
+        date: row.try_get("date")?,
+        faction: row.try_get("faction")?,
+        time: row.try_get("time")?,
+        donate_type: row.try_get("donate_type")?,
+        value: row.try_get("value")?,
+        who: Player {
+            name: row.try_get("who_name")?,
+            steam_id: row.try_get("who_steam_id")?,
+        },
+    })
+}
+
+const DONATE_COLUMNS: &str = "id, client_uuid, account_name, account_steam_id, date, faction, time, donate_type, value, who_name, who_steam_id";
+
+impl Storage for PostgresStorage {
+    fn migrate(&self, dry_run: bool) -> Result<MigrationReport> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])?;
+        let from = current_version(&mut tx)?;
+        if let Some((latest, _, _)) = MIGRATIONS.last() {
+            if from > *latest {
+                return Err(anyhow::anyhow!(
+                    "Database schema version {} is newer than this server ({}), refusing to start",
+                    from, latest
+                ));
+            }
+        }
+        tx.batch_execute("
+            CREATE TABLE IF NOT EXISTS schema_version (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL
+            );
+        ")?;
+        let mut applied = Vec::new();
+        for (version, name, sql) in MIGRATIONS.iter().filter(|(version, _, _)| *version > from) {
+            info!("{} migration {} ({})", if dry_run { "Checking" } else { "Applying" }, version, name);
+            tx.batch_execute(sql).with_context(|| format!("Migration {} ({}) failed", version, name))?;
+            tx.execute(
+                "INSERT INTO schema_version (version, name, applied_at) VALUES ($1, $2, $3)",
+                &[&(*version as i32), name, &Utc::now()],
+            )?;
+            applied.push(*name);
+        }
+        let to = if dry_run { from } else { current_version(&mut tx)? };
+        if dry_run {
+            tx.rollback()?;
+        } else {
+            tx.commit()?;
+        }
+        Ok(MigrationReport { from, to, applied })
+    }
+
+    fn register_client(&self, client_uuid: &str, secret: &str, info: &ServerInfo, state: ClientState) -> Result<()> {
+        self.db()?.execute("
+            INSERT INTO clients (uuid, server_name, registered_at, last_seen, secret, map, hostname, state) VALUES ($1, $2, $3, $3, $4, $5, $6, $7)
+            ON CONFLICT (uuid) DO UPDATE SET
+                server_name = excluded.server_name,
+                registered_at = excluded.registered_at,
+                last_seen = excluded.last_seen,
+                secret = excluded.secret,
+                map = excluded.map,
+                hostname = excluded.hostname,
+                notes = NULL,
+                name_overridden = FALSE,
+                state = excluded.state
+        ", &[&client_uuid, &display_name(client_uuid, info), &Utc::now(), &secret, &info.map, &info.hostname, &state.as_str()])?;
+        Ok(())
+    }
+
+    fn update_client_info(&self, client_uuid: &str, info: &ServerInfo) -> Result<()> {
+        self.db()?.execute("
+            UPDATE clients SET
+                server_name = CASE WHEN name_overridden THEN server_name ELSE $1 END,
+                map = $2,
+                hostname = $3
+            WHERE uuid = $4
+        ", &[&display_name(client_uuid, info), &info.map, &info.hostname, &client_uuid])?;
+        Ok(())
+    }
+
+    fn update_client(&self, client_uuid: &str, update: &UpdateClientRequest) -> Result<bool> {
+        let mut db = self.db()?;
+        if db.query_opt("SELECT 1 FROM clients WHERE uuid = $1 AND state != 'deleted'", &[&client_uuid])?.is_none() {
+            return Ok(false);
+        }
+        if let Some(server_name) = &update.server_name {
+            db.execute("UPDATE clients SET server_name = $1, name_overridden = TRUE WHERE uuid = $2", &[&server_name.trim(), &client_uuid])?;
+        }
+        if let Some(notes) = &update.notes {
+            let notes = Some(notes.trim()).filter(|notes| !notes.is_empty());
+            db.execute("UPDATE clients SET notes = $1 WHERE uuid = $2", &[&notes, &client_uuid])?;
+        }
+        Ok(true)
+    }
+
+    fn get_clients(&self) -> Result<Vec<ClientConnection>> {
+        let rows = self.db()?.query("SELECT uuid, server_name, registered_at, last_seen, map, hostname, notes, state FROM clients WHERE state != 'deleted'", &[])?;
+        rows.iter().map(|row| -> Result<ClientConnection> {
+            Ok(ClientConnection {
+                uuid: row.try_get("uuid")?,
+                server_name: row.try_get("server_name")?,
+                registered_at: row.try_get("registered_at")?,
+                last_seen: row.try_get("last_seen")?,
+                map: row.try_get("map")?,
+                hostname: row.try_get("hostname")?,
+                notes: row.try_get("notes")?,
+                state: ClientState::parse(row.try_get("state")?).unwrap_or_default(),
+            })
+        }).collect()
+    }
+
+    fn get_client_state(&self, client_uuid: &str) -> Result<Option<ClientState>> {
+        let row = self.db()?.query_opt("SELECT state FROM clients WHERE uuid = $1 AND state != 'deleted'", &[&client_uuid])?;
+        row.map(|row| -> Result<ClientState> {
+            let state: String = row.try_get(0)?;
+            ClientState::parse(&state).ok_or_else(|| anyhow::anyhow!("Unknown client state {}", state))
+        }).transpose()
+    }
+
+    fn set_client_state(&self, client_uuid: &str, state: ClientState) -> Result<bool> {
+        let updated = self.db()?.execute("UPDATE clients SET state = $1 WHERE uuid = $2 AND state != 'deleted'", &[&state.as_str(), &client_uuid])?;
+        Ok(updated > 0)
+    }
+
+    fn get_client_secret(&self, client_uuid: &str) -> Result<Option<String>> {
+        let row = self.db()?.query_opt("SELECT secret FROM clients WHERE uuid = $1", &[&client_uuid])?;
+        Ok(row.map(|row| row.try_get(0)).transpose()?.flatten())
+    }
+
+    fn set_client_secret(&self, client_uuid: &str, secret: &str) -> Result<()> {
+        self.db()?.execute("UPDATE clients SET secret = $1 WHERE uuid = $2", &[&secret, &client_uuid])?;
+        Ok(())
+    }
+
+    fn revoke_client_secret(&self, client_uuid: &str) -> Result<bool> {
+        let updated = self.db()?.execute("UPDATE clients SET secret = NULL WHERE uuid = $1 AND state != 'deleted'", &[&client_uuid])?;
+        Ok(updated > 0)
+    }
+
+    fn disable_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        self.retire_client("UPDATE clients SET state = 'disabled' WHERE uuid = $1 AND state != 'deleted'", client_uuid, pending)
+    }
+
+    fn delete_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        self.retire_client("UPDATE clients SET state = 'deleted', secret = NULL WHERE uuid = $1 AND state != 'deleted'", client_uuid, pending)
+    }
+
+    fn update_last_seen(&self, client_uuid: &str) -> Result<()> {
+        self.db()?.execute("UPDATE clients SET last_seen = $1 WHERE uuid = $2", &[&Utc::now(), &client_uuid])?;
+        Ok(())
+    }
+
+    fn create_message(&self, message: &Message) -> Result<u64> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        let message_id = insert_message(&mut tx, message)?;
+        tx.commit()?;
+        Ok(message_id)
+    }
+
+    fn get_pending_messages(&self, client_uuid: &str) -> Result<Vec<Message>> {
+        let rows = self.db()?.query(
+            "SELECT id, client_uuid, message_type, message_data, created_at, delivered_at, status, outcome, failure_reason FROM messages WHERE client_uuid = $1 AND status = 'pending' AND (retry_at IS NULL OR retry_at <= $2) ORDER BY id",
+            &[&client_uuid, &Utc::now()],
+        )?;
+        rows.iter().map(message_from_row).collect()
+    }
+
+    fn lease_messages(&self, message_ids: &[u64]) -> Result<()> {
+        self.db()?.execute(
+            "UPDATE messages SET status = 'leased', leased_at = $1 WHERE id = ANY($2) AND status = 'pending'",
+            &[&Utc::now(), &ids(message_ids)],
+        )?;
+        Ok(())
+    }
+
+    fn mark_messages_delivered(&self, client_uuid: &str, message_ids: &[u64]) -> Result<usize> {
+        let delivered = self.db()?.execute(
+            "UPDATE messages SET status = 'delivered', delivered_at = $1, leased_at = NULL WHERE id = ANY($2) AND client_uuid = $3 AND status = 'leased'",
+            &[&Utc::now(), &ids(message_ids), &client_uuid],
+        )?;
+        Ok(delivered as usize)
+    }
+
+    fn mark_messages_applied(&self, client_uuid: &str, message_ids: &[u64]) -> Result<usize> {
+        let applied = self.db()?.execute(
+            "UPDATE messages SET outcome = 'applied', failure_reason = NULL, status = 'delivered', delivered_at = COALESCE(delivered_at, $1), leased_at = NULL WHERE id = ANY($2) AND client_uuid = $3 AND status IN ('leased', 'delivered')",
+            &[&Utc::now(), &ids(message_ids), &client_uuid],
+        )?;
+        Ok(applied as usize)
+    }
+
+    fn mark_messages_failed(&self, client_uuid: &str, message_ids: &[u64], reason: &str, retry: &RetryPolicy) -> Result<usize> {
+        let now = Utc::now();
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        let rows = tx.query(
+            "SELECT id, attempts FROM messages WHERE id = ANY($1) AND client_uuid = $2 AND status IN ('leased', 'delivered') AND outcome IS DISTINCT FROM 'applied' FOR UPDATE",
+            &[&ids(message_ids), &client_uuid],
+        )?;
+        let mut failed = 0;
+        for row in rows {
+            let id: i64 = row.try_get(0)?;
+            let attempts = row.try_get::<_, i32>(1)? + 1;
+            let retry_at = retry.retry_at(attempts as u32, now);
+            let status = if retry_at.is_some() { "pending" } else { "cancelled" };
+            failed += tx.execute(
+                "UPDATE messages SET outcome = 'failed', failure_reason = $1, status = $2, attempts = $3, retry_at = $4, delivered_at = NULL, leased_at = NULL WHERE id = $5",
+                &[&reason, &status, &attempts, &retry_at, &id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(failed as usize)
+    }
+
+    fn release_expired_leases(&self, lease_timeout: chrono::Duration) -> Result<usize> {
+        let cutoff_time = Utc::now() - lease_timeout;
+        let released = self.db()?.execute(
+            "UPDATE messages SET status = 'pending', leased_at = NULL WHERE status = 'leased' AND leased_at < $1",
+            &[&cutoff_time],
+        )?;
+        Ok(released as usize)
+    }
+
+    fn prune_messages(&self, older_than: DateTime<Utc>, archive: &mut dyn FnMut(&[Message]) -> Result<()>) -> Result<usize> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        let rows = tx.query(
+            "SELECT id, client_uuid, message_type, message_data, created_at, delivered_at, status, outcome, failure_reason FROM messages
+            WHERE status IN ('delivered', 'cancelled') AND COALESCE(delivered_at, created_at) < $1 ORDER BY id FOR UPDATE",
+            &[&older_than],
+        )?;
+        let messages = rows.iter().map(message_from_row).collect::<Result<Vec<Message>>>()?;
+        if messages.is_empty() {
+            return Ok(0);
+        }
+        archive(&messages)?;
+        let message_ids: Vec<i64> = messages.iter().map(|message| message.id as i64).collect();
+        tx.execute("UPDATE donates SET message_id = NULL WHERE message_id = ANY($1)", &[&message_ids])?;
+        let pruned = tx.execute("DELETE FROM messages WHERE id = ANY($1)", &[&message_ids])?;
+        tx.commit()?;
+        Ok(pruned as usize)
+    }
+
+    fn get_donates(&self) -> Result<Vec<Donate>> {
+        let rows = self.db()?.query(&format!("SELECT {} FROM donates ORDER BY id", DONATE_COLUMNS), &[])?;
+        rows.iter().map(donate_from_row).collect()
+    }
+
+    fn delete_donate(&self, donate_id: u64) -> Result<Option<(Donate, String)>> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        let Some(donate) = select_donate(&mut tx, donate_id)? else {
+            return Ok(None);
+        };
+        let client_uuid = donate.client_uuid.clone().unwrap_or_default();
+        tx.execute("DELETE FROM donates WHERE id = $1", &[&(donate_id as i64)])?;
+        fail_point("donate_deleted")?;
+        insert_message(&mut tx, &donate_notification(&client_uuid, "donate_deleted", donate_id, &donate))?;
+        tx.commit()?;
+        Ok(Some((donate, client_uuid)))
+    }
+
+    fn update_donate(&self, donate_id: u64, donate: &Donate) -> Result<Option<String>> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        let Some(client_uuid) = select_donate(&mut tx, donate_id)?.and_then(|donate| donate.client_uuid) else {
+            return Ok(None);
+        };
+        tx.execute(
+            "UPDATE donates SET account_name = $1, account_steam_id = $2, who_name = $3, who_steam_id = $4, donate_type = $5, value = $6, faction = $7, date = $8, time = $9 WHERE id = $10",
+            &[
+                &donate.account.name,
+                &donate.account.steam_id,
+                &donate.who.name,
+                &donate.who.steam_id,
+                &donate.donate_type,
+                &donate.value,
+                &donate.faction,
+                &donate.date,
+                &donate.time,
+                &(donate_id as i64),
+            ],
+        )?;
+        fail_point("donate_updated")?;
+        let mut updated_donate = donate.clone();
+        updated_donate.id = Some(donate_id);
+        insert_message(&mut tx, &donate_notification(&client_uuid, "donate_updated", donate_id, &updated_donate))?;
+        tx.commit()?;
+        Ok(Some(client_uuid))
+    }
+
+    fn get_banned_addresses(&self) -> Result<Vec<IpAddr>> {
+        let rows = self.db()?.query("SELECT address FROM banned_addresses ORDER BY address", &[])?;
+        rows.iter().map(|row| {
+            let address: String = row.try_get(0)?;
+            address.parse().with_context(|| format!("Invalid banned address {}", address))
+        }).collect()
+    }
+
+    fn ban_address(&self, ip: IpAddr) -> Result<bool> {
+        let inserted = self.db()?.execute(
+            "INSERT INTO banned_addresses (address, banned_at) VALUES ($1, $2) ON CONFLICT (address) DO NOTHING",
+            &[&ip.to_string(), &Utc::now()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn unban_address(&self, ip: IpAddr) -> Result<bool> {
+        let deleted = self.db()?.execute("DELETE FROM banned_addresses WHERE address = $1", &[&ip.to_string()])?;
+        Ok(deleted > 0)
+    }
+
+    fn record_nonce(&self, client_uuid: &str, nonce: &str, signed_at: i64, expired_before: i64) -> Result<bool> {
+        let mut db = self.db()?;
+        let mut tx = db.transaction()?;
+        tx.execute("DELETE FROM request_nonces WHERE signed_at < $1", &[&expired_before])?;
+        let inserted = tx.execute(
+            "INSERT INTO request_nonces (client_uuid, nonce, signed_at) VALUES ($1, $2, $3) ON CONFLICT (client_uuid, nonce) DO NOTHING",
+            &[&client_uuid, &nonce, &signed_at],
+        )?;
+        tx.commit()?;
+        Ok(inserted > 0)
+    }
+}
+
+/// Inserts a message. A donate also gets its donates row, whose id is written into the payload.
+fn insert_message(tx: &mut Transaction, message: &Message) -> Result<u64> {
+    let message_id: i64 = tx.query_one(
+        "INSERT INTO messages (client_uuid, message_type, message_data, created_at, status) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        &[&message.client_uuid, &message.message_type, &message.message_data, &message.created_at, &message.status],
+    )?.try_get(0)?;
+    if message.message_type == "donate" {
+        fail_point("message_inserted")?;
+        let donate: Donate = serde_json::from_value(message.message_data.clone())?;
+        let donate_id: i64 = tx.query_one(
+            "INSERT INTO donates (message_id, client_uuid, account_name, account_steam_id, who_name, who_steam_id, donate_type, value, faction, date, time, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id",
+            &[&message_id, &message.client_uuid, &donate.account.name, &donate.account.steam_id, &donate.who.name, &donate.who.steam_id, &donate.donate_type, &donate.value, &donate.faction, &donate.date, &donate.time, &Utc::now()],
+        )?.try_get(0)?;
+        fail_point("donate_inserted")?;
+
+        tx.execute(
+            "UPDATE messages SET message_data = $1 WHERE id = $2",
+            &[&with_donate_id(&message.message_data, donate_id as u64), &message_id],
+        )?;
+    }
+    Ok(message_id as u64)
+}
+
+/// The donate, with its client, locked until the transaction ends.
+fn select_donate(tx: &mut Transaction, donate_id: u64) -> Result<Option<Donate>> {
+    let row = tx.query_opt(&format!("SELECT {} FROM donates WHERE id = $1 FOR UPDATE", DONATE_COLUMNS), &[&(donate_id as i64)])?;
+    row.as_ref().map(donate_from_row).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+    /// A throwaway cluster made with `initdb` and `pg_ctl` from PATH. It only listens on a
+    /// unix socket in its own directory and is stopped and removed on drop.
+    struct TestCluster(PathBuf);
+
+    impl TestCluster {
+        fn start(name: &str) -> Option<Self> {
+            let dir = std::env::temp_dir().join(format!("gmod_tcp_pg_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            let cluster = TestCluster(dir);
+            let data = cluster.0.join("data");
+            let initdb = Command::new("initdb")
+                .arg("-D").arg(&data)
+                .args(["-A", "trust", "-U", "postgres"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            if !initdb.is_ok_and(|status| status.success()) {
+                return None;
+            }
+            let started = Command::new("pg_ctl")
+                .arg("-D").arg(&data)
+                .arg("-l").arg(cluster.0.join("server.log"))
+                .arg("-o").arg(format!("-k {} -c listen_addresses=''", cluster.0.display()))
+                .args(["-w", "start"])
+                .stdout(Stdio::null())
+                .status();
+            started.is_ok_and(|status| status.success()).then_some(cluster)
+        }
+
+        fn url(&self) -> String {
+            format!("host={} user=postgres dbname=postgres", self.0.display())
+        }
+    }
+
+    impl Drop for TestCluster {
+        fn drop(&mut self) {
+            let _ = Command::new("pg_ctl")
+                .arg("-D").arg(self.0.join("data"))
+                .args(["-m", "immediate", "-w", "stop"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// The server `TEST_DATABASE_URL` points at, else a `TestCluster`. Panics when neither is
+    /// available, the tests are ignored by default so they never pass without running.
+    fn server(name: &str) -> (String, Option<TestCluster>) {
+        if let Ok(url) = std::env::var("TEST_DATABASE_URL") {
+            return (url, None);
+        }
+        let cluster = TestCluster::start(name).unwrap_or_else(|| panic!("No PostgreSQL to test against, set TEST_DATABASE_URL or put initdb and pg_ctl on PATH (not as root)"));
+        (cluster.url(), Some(cluster))
+    }
+
+    /// A database made for one check, dropped afterwards.
+    struct TempDatabase {
+        admin_url: String,
+        name: String,
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            if let Ok(mut admin) = postgres::Client::connect(&self.admin_url, NoTls) {
+                let _ = admin.batch_execute(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.name));
+            }
+        }
+    }
+
+    fn storage(admin_url: &str, check: &str) -> (PostgresStorage, TempDatabase) {
+        let name = format!("gmod_tcp_{}_{}_{}", check, std::process::id(), DATABASES.fetch_add(1, Ordering::Relaxed));
+        let mut admin = postgres::Client::connect(admin_url, NoTls).unwrap();
+        admin.batch_execute(&format!("CREATE DATABASE {}", name)).unwrap();
+        let database = TempDatabase { admin_url: admin_url.to_string(), name };
+
+        let mut config: postgres::Config = admin_url.parse().unwrap();
+        config.dbname(&database.name);
+        let storage = PostgresStorage { pool: Some(Pool::builder().max_size(2).build(PostgresConnectionManager::new(config, NoTls)).unwrap()) };
+        storage.migrate(false).unwrap();
+        (storage, database)
+    }
+
+    #[test]
+    #[ignore = "needs PostgreSQL: set TEST_DATABASE_URL or put initdb and pg_ctl on PATH (not as root)"]
+    fn conforms() {
+        let (admin_url, _cluster) = server("conforms");
+        conformance::run(|check| storage(&admin_url, check));
+    }
+
+    #[test]
+    #[ignore = "needs PostgreSQL: set TEST_DATABASE_URL or put initdb and pg_ctl on PATH (not as root)"]
+    fn dry_run_leaves_database_untouched() {
+        let (admin_url, _cluster) = server("dry_run");
+        let (storage, _database) = storage(&admin_url, "dry_run");
+        storage.db().unwrap().batch_execute("DROP TABLE donates, messages, clients, banned_addresses, request_nonces, schema_version").unwrap();
+
+        let report = storage.migrate(true).unwrap();
+        assert_eq!((report.from, report.to), (0, 0));
+        assert_eq!(report.applied, MIGRATIONS.iter().map(|(_, name, _)| *name).collect::<Vec<_>>());
+        let exists: bool = storage.db().unwrap().query_one("SELECT to_regclass('clients') IS NOT NULL", &[]).unwrap().get(0);
+        assert!(!exists);
+    }
+}
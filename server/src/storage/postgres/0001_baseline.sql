@@ -0,0 +1,43 @@
+CREATE TABLE IF NOT EXISTS clients (
+    uuid TEXT PRIMARY KEY,
+    server_name TEXT NOT NULL,
+    registered_at TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    secret TEXT,
+    map TEXT,
+    hostname TEXT,
+    notes TEXT,
+    name_overridden BOOLEAN NOT NULL DEFAULT FALSE,
+    state TEXT NOT NULL DEFAULT 'active'
+);
+
+CREATE TABLE IF NOT EXISTS messages (
+    id BIGSERIAL PRIMARY KEY,
+    client_uuid TEXT NOT NULL REFERENCES clients(uuid),
+    message_type TEXT NOT NULL,
+    message_data JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    delivered_at TIMESTAMPTZ,
+    status TEXT NOT NULL DEFAULT 'pending',
+    leased_at TIMESTAMPTZ,
+    outcome TEXT,
+    failure_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS messages_client_status ON messages (client_uuid, status);
+
+CREATE TABLE IF NOT EXISTS donates (
+    id BIGSERIAL PRIMARY KEY,
+    message_id BIGINT REFERENCES messages(id),
+    client_uuid TEXT NOT NULL REFERENCES clients(uuid),
+    account_name TEXT DEFAULT NULL,
+    account_steam_id TEXT NOT NULL,
+    who_name TEXT NOT NULL,
+    who_steam_id TEXT DEFAULT NULL,
+    donate_type TEXT NOT NULL,
+    value TEXT NOT NULL,
+    faction TEXT NOT NULL,
+    date TIMESTAMPTZ NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS banned_addresses (
+    address TEXT PRIMARY KEY,
+    banned_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS request_nonces (
+    client_uuid TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    signed_at BIGINT NOT NULL,
+    PRIMARY KEY (client_uuid, nonce)
+);
+CREATE INDEX IF NOT EXISTS idx_request_nonces_signed_at ON request_nonces(signed_at);
@@ -0,0 +1,503 @@
+use super::{display_name, donate_notification, fail_point, migrations, with_donate_id, MigrationReport, RetryPolicy, Storage};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use gmod_tcp_shared::types::{ClientConnection, ClientState, Donate, Message, PendingMessages, Player, ServerInfo, UpdateClientRequest};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+/// How long a connection waits for another one holding the write lock before failing with SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A database file shared by a pool of connections. Every connection uses WAL, so readers
+/// do not block the writer, waits for locks up to `BUSY_TIMEOUT` and enforces foreign keys.
+pub struct SqliteStorage {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path, pool_size: u32) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let manager = SqliteConnectionManager::file(path).with_init(|db| {
+            db.busy_timeout(BUSY_TIMEOUT)?;
+            db.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+            db.execute_batch("PRAGMA foreign_keys = ON;")
+        });
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .build(manager)
+            .with_context(|| format!("Failed to open database {:?}", path))?;
+        info!("SQLite database {} opened with up to {} connection(s)", path.display(), pool_size);
+        Ok(SqliteStorage { pool })
+    }
+
+    fn db(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+
+    /// Moves a client out of service with `update` and settles its messages in the same transaction.
+    fn retire_client(&self, update: &str, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        let mut db = self.db()?;
+        let tx = db.transaction()?;
+        if tx.execute(update, params![client_uuid])? == 0 {
+            return Ok(None);
+        }
+        fail_point("client_retired")?;
+        let settled = match pending {
+            PendingMessages::Keep => 0,
+            PendingMessages::Cancel => tx.execute(
+                "UPDATE messages SET status = 'cancelled', leased_at = NULL WHERE client_uuid = ? AND status IN ('pending', 'leased')",
+                params![client_uuid]
+            )?,
+            PendingMessages::Reassign { to } => {
+                tx.execute(
+                    "UPDATE donates SET client_uuid = ? WHERE message_id IN (
+                        SELECT id FROM messages WHERE client_uuid = ? AND status IN ('pending', 'leased')
+                    )",
+                    params![to, client_uuid]
+                )?;
+                tx.execute(
+                    "UPDATE messages SET client_uuid = ?, status = 'pending', leased_at = NULL, attempts = 0, retry_at = NULL WHERE client_uuid = ? AND status IN ('pending', 'leased')",
+                    params![to, client_uuid]
+                )?
+            }
+        };
+        tx.commit()?;
+        Ok(Some(settled))
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn migrate(&self, dry_run: bool) -> Result<MigrationReport> {
+        let mut db = self.db()?;
+        let from = migrations::current_version(&db)?;
+        let applied = migrations::migrate(&mut db, dry_run)?;
+        Ok(MigrationReport {
+            from,
+            to: migrations::current_version(&db)?,
+            applied: applied.iter().map(|migration| migration.name).collect(),
+        })
+    }
+
+    fn register_client(&self, client_uuid: &str, secret: &str, info: &ServerInfo, state: ClientState) -> Result<()> {
+        self.db()?.execute("
+            INSERT INTO clients (uuid, server_name, registered_at, last_seen, secret, map, hostname, state) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (uuid) DO UPDATE SET
+                server_name = excluded.server_name,
+                registered_at = excluded.registered_at,
+                last_seen = excluded.last_seen,
+                secret = excluded.secret,
+                map = excluded.map,
+                hostname = excluded.hostname,
+                notes = NULL,
+                name_overridden = 0,
+                state = excluded.state;
+        ", params![client_uuid, display_name(client_uuid, info), Utc::now().to_rfc3339(), Utc::now().to_rfc3339(), secret, info.map, info.hostname, state.as_str()])?;
+        Ok(())
+    }
+
+    fn update_client_info(&self, client_uuid: &str, info: &ServerInfo) -> Result<()> {
+        self.db()?.execute("
+            UPDATE clients SET
+                server_name = CASE WHEN name_overridden = 0 THEN ? ELSE server_name END,
+                map = ?,
+                hostname = ?
+            WHERE uuid = ?
+        ", params![display_name(client_uuid, info), info.map, info.hostname, client_uuid])?;
+        Ok(())
+    }
+
+    fn update_client(&self, client_uuid: &str, update: &UpdateClientRequest) -> Result<bool> {
+        let db = self.db()?;
+        if db.query_row("SELECT COUNT(*) FROM clients WHERE uuid = ? AND state != 'deleted'", [client_uuid], |row| row.get::<_, i32>(0))? == 0 {
+            return Ok(false);
+        }
+        if let Some(server_name) = &update.server_name {
+            db.execute("UPDATE clients SET server_name = ?, name_overridden = 1 WHERE uuid = ?", params![server_name.trim(), client_uuid])?;
+        }
+        if let Some(notes) = &update.notes {
+            let notes = Some(notes.trim()).filter(|notes| !notes.is_empty());
+            db.execute("UPDATE clients SET notes = ? WHERE uuid = ?", params![notes, client_uuid])?;
+        }
+        Ok(true)
+    }
+
+    fn get_clients(&self) -> Result<Vec<ClientConnection>> {
+        let db = self.db()?;
+        let mut stmt = db.prepare("SELECT uuid, server_name, registered_at, last_seen, map, hostname, notes, state FROM clients WHERE state != 'deleted'")?;
+        let clients: Result<Vec<ClientConnection>, _> = stmt.query_map([], |row| {
+            let registered_at_str: String = row.get(2)?;
+            let last_seen_str: String = row.get(3)?;
+            Ok(ClientConnection {
+                uuid: row.get(0)?,
+                server_name: row.get(1)?,
+                registered_at: DateTime::parse_from_rfc3339(&registered_at_str).map_err(|_| rusqlite::Error::InvalidColumnType(2, "registered_at".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                last_seen: DateTime::parse_from_rfc3339(&last_seen_str).map_err(|_| rusqlite::Error::InvalidColumnType(3, "last_seen".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                map: row.get(4)?,
+                hostname: row.get(5)?,
+                notes: row.get(6)?,
+                state: ClientState::parse(&row.get::<_, String>(7)?).unwrap_or_default(),
+            })
+        })?.collect();
+        clients.map_err(|e| anyhow::anyhow!("Database error: {}", e))
+    }
+
+    fn get_client_state(&self, client_uuid: &str) -> Result<Option<ClientState>> {
+        let state: Option<String> = match self.db()?.query_row("SELECT state FROM clients WHERE uuid = ? AND state != 'deleted'", [client_uuid], |row| row.get(0)) {
+            Ok(state) => Some(state),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(anyhow::anyhow!("Database error: {}", e)),
+        };
+        state.map(|state| ClientState::parse(&state).ok_or_else(|| anyhow::anyhow!("Unknown client state {}", state))).transpose()
+    }
+
+    fn set_client_state(&self, client_uuid: &str, state: ClientState) -> Result<bool> {
+        let updated = self.db()?.execute("UPDATE clients SET state = ? WHERE uuid = ? AND state != 'deleted'", params![state.as_str(), client_uuid])?;
+        Ok(updated > 0)
+    }
+
+    fn get_client_secret(&self, client_uuid: &str) -> Result<Option<String>> {
+        match self.db()?.query_row("SELECT secret FROM clients WHERE uuid = ?", [client_uuid], |row| row.get(0)) {
+            Ok(secret) => Ok(secret),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(anyhow::anyhow!("Database error: {}", e)),
+        }
+    }
+
+    fn set_client_secret(&self, client_uuid: &str, secret: &str) -> Result<()> {
+        self.db()?.execute("UPDATE clients SET secret = ? WHERE uuid = ?", params![secret, client_uuid])?;
+        Ok(())
+    }
+
+    fn revoke_client_secret(&self, client_uuid: &str) -> Result<bool> {
+        let updated = self.db()?.execute("UPDATE clients SET secret = NULL WHERE uuid = ? AND state != 'deleted'", params![client_uuid])?;
+        Ok(updated > 0)
+    }
+
+    fn disable_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        self.retire_client("UPDATE clients SET state = 'disabled' WHERE uuid = ? AND state != 'deleted'", client_uuid, pending)
+    }
+
+    fn delete_client(&self, client_uuid: &str, pending: &PendingMessages) -> Result<Option<usize>> {
+        self.retire_client("UPDATE clients SET state = 'deleted', secret = NULL WHERE uuid = ? AND state != 'deleted'", client_uuid, pending)
+    }
+
+    fn update_last_seen(&self, client_uuid: &str) -> Result<()> {
+        self.db()?.execute("UPDATE clients SET last_seen = ? WHERE uuid = ?", params![Utc::now().to_rfc3339(), client_uuid])?;
+        Ok(())
+    }
+
+    fn create_message(&self, message: &Message) -> Result<u64> {
+        let mut db = self.db()?;
+        let tx = db.transaction()?;
+        let message_id = insert_message(&tx, message)?;
+        tx.commit()?;
+        Ok(message_id)
+    }
+
+    fn get_pending_messages(&self, client_uuid: &str) -> Result<Vec<Message>> {
+        let db = self.db()?;
+        let mut stmt = db.prepare(&format!(
+            "SELECT {} FROM messages WHERE client_uuid = ? AND status = 'pending' AND (retry_at IS NULL OR retry_at <= ?) ORDER BY id",
+            MESSAGE_COLUMNS
+        ))?;
+        let messages: Result<Vec<Message>, _> = stmt.query_map(params![client_uuid, Utc::now().to_rfc3339()], message_from_row)?.collect();
+        messages.map_err(|e| anyhow::anyhow!("Database error: {}", e))
+    }
+
+    fn lease_messages(&self, ids: &[u64]) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let db = self.db()?;
+        for id in ids {
+            db.execute("UPDATE messages SET status = 'leased', leased_at = ? WHERE id = ? AND status = 'pending'", params![now, id])?;
+        }
+        Ok(())
+    }
+
+    fn mark_messages_delivered(&self, client_uuid: &str, ids: &[u64]) -> Result<usize> {
+        let now = Utc::now().to_rfc3339();
+        let db = self.db()?;
+        let mut delivered = 0;
+        for id in ids {
+            delivered += db.execute(
+                "UPDATE messages SET status = 'delivered', delivered_at = ?, leased_at = NULL WHERE id = ? AND client_uuid = ? AND status = 'leased'",
+                params![now, id, client_uuid]
+            )?;
+        }
+        Ok(delivered)
+    }
+
+    fn mark_messages_applied(&self, client_uuid: &str, ids: &[u64]) -> Result<usize> {
+        let now = Utc::now().to_rfc3339();
+        let db = self.db()?;
+        let mut applied = 0;
+        for id in ids {
+            applied += db.execute(
+                "UPDATE messages SET outcome = 'applied', failure_reason = NULL, status = 'delivered', delivered_at = COALESCE(delivered_at, ?), leased_at = NULL WHERE id = ? AND client_uuid = ? AND status IN ('leased', 'delivered')",
+                params![now, id, client_uuid]
+            )?;
+        }
+        Ok(applied)
+    }
+
+    fn mark_messages_failed(&self, client_uuid: &str, ids: &[u64], reason: &str, retry: &RetryPolicy) -> Result<usize> {
+        let now = Utc::now();
+        let mut db = self.db()?;
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut failed = 0;
+        for id in ids {
+            let attempts: Option<u32> = tx.query_row(
+                "SELECT attempts FROM messages WHERE id = ? AND client_uuid = ? AND status IN ('leased', 'delivered') AND outcome IS NOT 'applied'",
+                params![id, client_uuid],
+                |row| row.get(0)
+            ).optional()?;
+            let Some(attempts) = attempts.map(|attempts| attempts + 1) else {
+                continue;
+            };
+            let retry_at = retry.retry_at(attempts, now);
+            let status = if retry_at.is_some() { "pending" } else { "cancelled" };
+            failed += tx.execute(
+                "UPDATE messages SET outcome = 'failed', failure_reason = ?, status = ?, attempts = ?, retry_at = ?, delivered_at = NULL, leased_at = NULL WHERE id = ?",
+                params![reason, status, attempts, retry_at.map(|at| at.to_rfc3339()), id]
+            )?;
+        }
+        tx.commit()?;
+        Ok(failed)
+    }
+
+    fn release_expired_leases(&self, lease_timeout: chrono::Duration) -> Result<usize> {
+        let cutoff_time = (Utc::now() - lease_timeout).to_rfc3339();
+        let released = self.db()?.execute(
+            "UPDATE messages SET status = 'pending', leased_at = NULL WHERE status = 'leased' AND leased_at < ?",
+            params![cutoff_time]
+        )?;
+        Ok(released)
+    }
+
+    fn prune_messages(&self, older_than: DateTime<Utc>, archive: &mut dyn FnMut(&[Message]) -> Result<()>) -> Result<usize> {
+        const FINISHED: &str = "status IN ('delivered', 'cancelled') AND COALESCE(delivered_at, created_at) < ?";
+        let cutoff_time = older_than.to_rfc3339();
+        let mut db = self.db()?;
+        // Immediate, so no other write lands between archiving the messages and deleting them.
+        let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let messages = {
+            let mut stmt = tx.prepare(&format!("SELECT {} FROM messages WHERE {} ORDER BY id", MESSAGE_COLUMNS, FINISHED))?;
+            let messages: Result<Vec<Message>, _> = stmt.query_map([&cutoff_time], message_from_row)?.collect();
+            messages.map_err(|e| anyhow::anyhow!("Database error: {}", e))?
+        };
+        if messages.is_empty() {
+            return Ok(0);
+        }
+        archive(&messages)?;
+        tx.execute(&format!("UPDATE donates SET message_id = NULL WHERE message_id IN (SELECT id FROM messages WHERE {})", FINISHED), [&cutoff_time])?;
+        let pruned = tx.execute(&format!("DELETE FROM messages WHERE {}", FINISHED), [&cutoff_time])?;
+        tx.commit()?;
+        Ok(pruned)
+    }
+
+    fn get_donates(&self) -> Result<Vec<Donate>> {
+        let db = self.db()?;
+        let mut stmt = db.prepare("SELECT id, client_uuid, account_name, account_steam_id, date, faction, time, donate_type, value, who_name, who_steam_id FROM donates ORDER BY id")?;
+        let donates: Result<Vec<Donate>, _> = stmt.query_map([], |row| {
+            let date_str: String = row.get(4)?;
+            let time_str: String = row.get(6)?;
+            Ok(Donate {
+                id: Some(row.get(0)?),
+                client_uuid: Some(row.get(1)?),
+                account: Player {
+                    name: row.get(2)?,
+                    steam_id: row.get(3)?,
+                },
+                date: DateTime::parse_from_rfc3339(&date_str).map_err(|_| rusqlite::Error::InvalidColumnType(4, "date".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                faction: row.get(5)?,
+                time: DateTime::parse_from_rfc3339(&time_str).map_err(|_| rusqlite::Error::InvalidColumnType(6, "time".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                donate_type: row.get(7)?,
+                value: row.get(8)?,
+                who: Player {
+                    name: row.get(9)?,
+                    steam_id: row.get(10)?,
+                },
+            })
+        })?.collect();
+        donates.map_err(|e| anyhow::anyhow!("Database error: {}", e))
+    }
+
+    fn delete_donate(&self, donate_id: u64) -> Result<Option<(Donate, String)>> {
+        let mut db = self.db()?;
+        let tx = db.transaction()?;
+        let Some((donate, client_uuid)) = select_donate(&tx, donate_id)? else {
+            return Ok(None);
+        };
+        tx.execute("DELETE FROM donates WHERE id = ?", params![donate_id])?;
+        fail_point("donate_deleted")?;
+        insert_message(&tx, &donate_notification(&client_uuid, "donate_deleted", donate_id, &donate))?;
+        tx.commit()?;
+        Ok(Some((donate, client_uuid)))
+    }
+
+    fn update_donate(&self, donate_id: u64, donate: &Donate) -> Result<Option<String>> {
+        let mut db = self.db()?;
+        let tx = db.transaction()?;
+        let Some((_, client_uuid)) = select_donate(&tx, donate_id)? else {
+            return Ok(None);
+        };
+        tx.execute(
+            "UPDATE donates SET account_name = ?, account_steam_id = ?, who_name = ?, who_steam_id = ?, donate_type = ?, value = ?, faction = ?, date = ?, time = ? WHERE id = ?",
+            params![
+                donate.account.name,
+                donate.account.steam_id,
+                donate.who.name,
+                donate.who.steam_id,
+                donate.donate_type,
+                donate.value,
+                donate.faction,
+                donate.date.to_rfc3339(),
+                donate.time.to_rfc3339(),
+                donate_id
+            ]
+        )?;
+        fail_point("donate_updated")?;
+        let mut updated_donate = donate.clone();
+        updated_donate.id = Some(donate_id);
+        insert_message(&tx, &donate_notification(&client_uuid, "donate_updated", donate_id, &updated_donate))?;
+        tx.commit()?;
+        Ok(Some(client_uuid))
+    }
+
+    fn get_banned_addresses(&self) -> Result<Vec<IpAddr>> {
+        let db = self.db()?;
+        let mut stmt = db.prepare("SELECT address FROM banned_addresses ORDER BY address")?;
+        let addresses = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+        addresses.iter().map(|address| address.parse().with_context(|| format!("Invalid banned address {}", address))).collect()
+    }
+
+    fn ban_address(&self, ip: IpAddr) -> Result<bool> {
+        let inserted = self.db()?.execute(
+            "INSERT INTO banned_addresses (address, banned_at) VALUES (?, ?) ON CONFLICT (address) DO NOTHING",
+            params![ip.to_string(), Utc::now().to_rfc3339()]
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn unban_address(&self, ip: IpAddr) -> Result<bool> {
+        let deleted = self.db()?.execute("DELETE FROM banned_addresses WHERE address = ?", params![ip.to_string()])?;
+        Ok(deleted > 0)
+    }
+
+    fn record_nonce(&self, client_uuid: &str, nonce: &str, signed_at: i64, expired_before: i64) -> Result<bool> {
+        let mut db = self.db()?;
+        let tx = db.transaction()?;
+        tx.execute("DELETE FROM request_nonces WHERE signed_at < ?", params![expired_before])?;
+        let inserted = tx.execute(
+            "INSERT INTO request_nonces (client_uuid, nonce, signed_at) VALUES (?, ?, ?) ON CONFLICT (client_uuid, nonce) DO NOTHING",
+            params![client_uuid, nonce, signed_at]
+        )?;
+        tx.commit()?;
+        Ok(inserted > 0)
+    }
+}
+
+const MESSAGE_COLUMNS: &str = "id, client_uuid, message_type, message_data, created_at, delivered_at, status, outcome, failure_reason";
+
+fn message_from_row(row: &Row) -> rusqlite::Result<Message> {
+    let created_at_str: String = row.get(4)?;
+    let delivered_at_str: Option<String> = row.get(5)?;
+    Ok(Message {
+        id: row.get(0)?,
+        client_uuid: row.get(1)?,
+        message_type: row.get(2)?,
+        message_data: serde_json::from_str(&row.get::<_, String>(3)?).map_err(|_| rusqlite::Error::InvalidColumnType(0, "message_data".to_string(), rusqlite::types::Type::Text))?,
+        created_at: DateTime::parse_from_rfc3339(&created_at_str).map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+        delivered_at: delivered_at_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok().map(|dt| dt.with_timezone(&Utc))),
+        status: row.get(6)?,
+        outcome: row.get(7)?,
+        failure_reason: row.get(8)?,
+    })
+}
+
+/// Inserts a message. A donate also gets its donates row, whose id is written into the payload.
+fn insert_message(db: &Connection, message: &Message) -> Result<u64> {
+    db.execute("INSERT INTO messages (client_uuid, message_type, message_data, created_at, status) VALUES (?, ?, ?, ?, ?);", params![message.client_uuid, message.message_type, serde_json::to_string(&message.message_data)?, message.created_at.to_rfc3339(), message.status])?;
+    let message_id = db.last_insert_rowid() as u64;
+    if message.message_type == "donate" {
+        fail_point("message_inserted")?;
+        let donate: Donate = serde_json::from_value(message.message_data.clone())?;
+        db.execute("INSERT INTO donates (message_id, client_uuid, account_name, account_steam_id, who_name, who_steam_id, donate_type, value, faction, date, time, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);", params![message_id, message.client_uuid, donate.account.name, donate.account.steam_id, donate.who.name, donate.who.steam_id, donate.donate_type, donate.value, donate.faction, donate.date.to_rfc3339(), donate.time.to_rfc3339(), Utc::now().to_rfc3339()])?;
+        let donate_id = db.last_insert_rowid() as u64;
+        fail_point("donate_inserted")?;
+
+        db.execute(
+            "UPDATE messages SET message_data = ? WHERE id = ?",
+            params![serde_json::to_string(&with_donate_id(&message.message_data, donate_id))?, message_id]
+        )?;
+    }
+    Ok(message_id)
+}
+
+fn select_donate(db: &Connection, donate_id: u64) -> Result<Option<(Donate, String)>> {
+    let mut stmt = db.prepare("SELECT id, account_name, account_steam_id, date, faction, time, donate_type, value, who_name, who_steam_id, client_uuid FROM donates WHERE id = ?")?;
+    match stmt.query_row(params![donate_id], |row| {
+        let date_str: String = row.get(3)?;
+        let time_str: String = row.get(5)?;
+        let client_uuid: String = row.get(10)?;
+        Ok((
+            Donate {
+                id: Some(row.get(0)?),
+                client_uuid: Some(client_uuid.clone()),
+                account: Player {
+                    name: row.get(1)?,
+                    steam_id: row.get(2)?,
+                },
+                date: DateTime::parse_from_rfc3339(&date_str).map_err(|_| rusqlite::Error::InvalidColumnType(3, "date".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                faction: row.get(4)?,
+                time: DateTime::parse_from_rfc3339(&time_str).map_err(|_| rusqlite::Error::InvalidColumnType(5, "time".to_string(), rusqlite::types::Type::Text))?.with_timezone(&Utc),
+                donate_type: row.get(6)?,
+                value: row.get(7)?,
+                who: Player {
+                    name: row.get(8)?,
+                    steam_id: row.get(9)?,
+                },
+            },
+            client_uuid,
+        ))
+    }) {
+        Ok(val) => Ok(Some(val)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("Database error: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{self, TempDb};
+
+    fn storage(name: &str) -> (SqliteStorage, TempDb) {
+        let file = TempDb::new(name);
+        let storage = SqliteStorage::open(&file.0, 2).unwrap();
+        storage.migrate(false).unwrap();
+        (storage, file)
+    }
+
+    #[test]
+    fn conforms() {
+        conformance::run(storage);
+    }
+
+    #[test]
+    fn connections_use_wal_and_foreign_keys() {
+        let (storage, _file) = storage("pragmas");
+        let db = storage.db().unwrap();
+        let journal_mode: String = db.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        let foreign_keys: i64 = db.query_row("PRAGMA foreign_keys", [], |row| row.get(0)).unwrap();
+        assert_eq!(journal_mode, "wal");
+        assert_eq!(foreign_keys, 1);
+    }
+}
@@ -1,50 +1,171 @@
 use tokio::net::TcpListener;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, Notify};
+use tokio_rustls::TlsAcceptor;
+use anyhow::{Context, Result};
 use chrono::Utc;
-use tokio::net::TcpStream;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::net::IpAddr;
+#[cfg(test)]
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tracing::{info, error};
+use tracing::{info, warn, error};
 
-use gmod_tcp_shared::types::{Message, Donate, ClientRequest, ServerResponse};
+use gmod_tcp_shared::auth;
+use gmod_tcp_shared::framing::{FrameCodec, FrameError, DEFAULT_MAX_FRAME_SIZE, DEFAULT_READ_TIMEOUT};
+use gmod_tcp_shared::protocol::{ClientFrame, Request, Response, PROTOCOL_VERSION};
+use gmod_tcp_shared::types::{ClientState, Message, Donate, ServerInfo};
+
+use crate::storage::{RetryPolicy, Storage};
+use crate::limits::{ConnectionLimits, RateKey};
+use crate::retention::RetentionPolicy;
+
+const DEFAULT_MESSAGE_LEASE_SECS: u64 = 300;
+const DEFAULT_SESSION_IDLE_SECS: u64 = 90;
+const DEFAULT_PENDING_RECHECK_SECS: u64 = 15;
 
 pub struct TcpServer {
     listener: Arc<TcpListener>,
+    storage: Arc<dyn Storage>,
+    tls_acceptor: Option<TlsAcceptor>,
+    lease_timeout: Duration,
+    session_idle_timeout: Duration,
+    /// How often a push session looks for messages and state changes it was not woken for,
+    /// like those made by another server sharing the database.
+    pending_recheck: Duration,
+    codec: FrameCodec,
+    limits: ConnectionLimits,
+    retention: RetentionPolicy,
+    retry: RetryPolicy,
+    require_approval: bool,
+    sessions: Mutex<HashMap<String, Arc<Notify>>>,
 }
 
 impl TcpServer {
-    pub async fn new() -> Result<Self> {
+    /// Binds the client listener to `HOST` and `PORT` from `.env`.
+    pub async fn new(storage: Arc<dyn Storage>) -> Result<Self> {
         dotenvy::dotenv().ok();
         let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
         let port = std::env::var("PORT").unwrap_or_else(|_| "25565".to_string());
         let addr = format!("{}:{}", host, port);
-        let listener = TcpListener::bind(&addr).await.unwrap();
+        let listener = TcpListener::bind(&addr).await.with_context(|| format!("Failed to bind {}", addr))?;
         info!("TCP server bound to {}", addr);
+        Self::with_listener(storage, listener).await
+    }
+    /// Serves clients on an already bound listener, the rest is read from `.env` like in `new`.
+    pub async fn with_listener(storage: Arc<dyn Storage>, listener: TcpListener) -> Result<Self> {
+        dotenvy::dotenv().ok();
+        let lease_secs = std::env::var("MESSAGE_LEASE_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_MESSAGE_LEASE_SECS);
+        let session_idle_secs = std::env::var("SESSION_IDLE_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SESSION_IDLE_SECS);
+        let pending_recheck_secs = std::env::var("PENDING_RECHECK_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_PENDING_RECHECK_SECS);
+        let max_frame_size = std::env::var("MAX_FRAME_SIZE")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+        let read_timeout = std::env::var("READ_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_READ_TIMEOUT);
+        let require_approval = std::env::var("REQUIRE_CLIENT_APPROVAL")
+            .map(|s| matches!(s.trim(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        let tls_acceptor = crate::tls::load_acceptor()?;
+        let limits = ConnectionLimits::from_env();
+        let banned = {
+            let storage = Arc::clone(&storage);
+            tokio::task::spawn_blocking(move || storage.get_banned_addresses()).await??
+        };
+        if !banned.is_empty() {
+            info!("Restored {} banned address(es) from the database", banned.len());
+        }
+        for ip in banned {
+            limits.ban(ip);
+        }
+        info!("Unacknowledged messages return to pending after {} seconds", lease_secs);
+        if require_approval {
+            info!("New clients wait for approval before they receive messages");
+        }
         Ok(Self { 
             listener: Arc::new(listener), 
+            storage,
+            tls_acceptor,
+            lease_timeout: Duration::from_secs(lease_secs),
+            session_idle_timeout: Duration::from_secs(session_idle_secs),
+            pending_recheck: Duration::from_secs(pending_recheck_secs),
+            codec: FrameCodec::new(max_frame_size, Some(read_timeout)),
+            limits,
+            retention: RetentionPolicy::from_env(),
+            retry: RetryPolicy::from_env(),
+            require_approval,
+            sessions: Mutex::new(HashMap::new()),
         })
     }
     pub async fn listen(self: Arc<Self>) -> Result<()> {
         let clone_self = Arc::clone(&self);
         tokio::spawn(async move {
             loop {
-                if let Err(e) = clone_self.clear_delivered_messages().await {
-                    error!("Error clearing delivered messages: {}", e);
-                };
-                info!("Cleared delivered messages");
+                match clone_self.prune_messages().await {
+                    Ok(0) => {}
+                    Ok(pruned) => info!("Pruned {} finished message(s), their donates are kept", pruned),
+                    Err(e) => error!("Error pruning messages: {}", e),
+                }
                 tokio::time::sleep(Duration::from_secs(60 * 60)).await;
             }
         });
+        let lease_clone = Arc::clone(&self);
+        tokio::spawn(async move {
+            let lease_timeout = chrono::Duration::from_std(lease_clone.lease_timeout).unwrap_or(chrono::Duration::MAX);
+            loop {
+                match lease_clone.release_expired_leases(lease_timeout).await {
+                    Ok(0) => {}
+                    Ok(released) => {
+                        info!("Returned {} unacknowledged message(s) to pending", released);
+                        lease_clone.notify_all_sessions();
+                    }
+                    Err(e) => error!("Error releasing expired leases: {}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
         let another_one_clone = Arc::clone(&self);
         tokio::spawn(async move {
             loop {
                 match another_one_clone.listener.accept().await {
                     Ok((socket, addr)) => {
+                        let permit = match another_one_clone.limits.admit(addr.ip()) {
+                            Ok(permit) => permit,
+                            Err(rejection) => {
+                                warn!("Rejected TCP connection from {}: {}", addr, rejection);
+                                continue;
+                            }
+                        };
                         info!("New TCP connection from {}", addr);
                         let server_clone = Arc::clone(&another_one_clone);
                         tokio::spawn(async move {
-                            if let Err(e) = server_clone.handle_socket_messsages(socket).await {
+                            let _permit = permit;
+                            let result = match &server_clone.tls_acceptor {
+                                Some(acceptor) => {
+                                    match tokio::time::timeout(Duration::from_secs(10), acceptor.accept(socket)).await {
+                                        Ok(Ok(tls_socket)) => server_clone.handle_socket_messsages(tls_socket, addr.ip()).await,
+                                        Ok(Err(e)) => Err(anyhow::anyhow!("TLS handshake failed: {}", e)),
+                                        Err(_) => Err(anyhow::anyhow!("TLS handshake timed out")),
+                                    }
+                                }
+                                None => server_clone.handle_socket_messsages(socket, addr.ip()).await,
+                            };
+                            if let Err(e) = result {
                                 error!("Error handling socket messages from {}: {}", addr, e);
                             };
                         });
@@ -62,70 +183,307 @@ impl TcpServer {
         }
     }
 
-    async fn read_message(socket: &mut TcpStream) -> Result<Vec<u8>> {
-        let mut length_bytes = [0u8; 4];
-        socket.read_exact(&mut length_bytes).await?;
-        let length = u32::from_le_bytes(length_bytes) as usize;
-        let mut buffer = vec![0u8; length];
-        socket.read_exact(&mut buffer).await?;
-        Ok(buffer)
-    }
-
-    async fn write_message(socket: &mut TcpStream, data: &[u8]) -> Result<()> {
-        let length = data.len() as u32;
-        socket.write_all(&length.to_le_bytes()).await?;
-        socket.write_all(data).await?;
-        socket.flush().await?;
+    async fn write_response<W: AsyncWrite + Unpin>(&self, socket: &mut W, response: &Response) -> Result<()> {
+        self.codec.write_frame(socket, &serde_json::to_vec(response)?).await?;
         Ok(())
     }
 
-    pub async fn handle_socket_messsages(&self, mut socket: TcpStream) -> Result<()> {
-        let message_data = Self::read_message(&mut socket).await?;
-        let request: ClientRequest = serde_json::from_slice(&message_data)?;
-        let client_uuid = request.uuid.clone();
+    pub async fn handle_socket_messsages<S>(&self, mut socket: S, ip: IpAddr) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let message_data = match self.codec.read_frame(&mut socket).await {
+            Ok(data) => data,
+            Err(e @ FrameError::TooLarge { .. }) => {
+                warn!("Rejected frame: {}", e);
+                return self.write_response(&mut socket, &Response::error(e.to_string())).await;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let frame = match ClientFrame::parse(&message_data) {
+            Ok(frame) => frame,
+            Err(response) => {
+                warn!("Rejected request: {:?}", response);
+                return self.write_response(&mut socket, &response).await;
+            }
+        };
+        let client_uuid = frame.uuid.clone();
         
-        info!("Received request: action={}, uuid={}", request.action, client_uuid);
+        info!("Received request: action={}, uuid={}", frame.request.action(), client_uuid);
         
-        if request.action == "pool" {
-            self.proof_client(client_uuid.clone()).await?;
-            let mut messages = self.get_pending_messages(client_uuid.clone()).await?;
-            info!("Polling request from client {}: {} messages found", client_uuid, messages.len());
-            let response = ServerResponse {
-                status: "ok".to_string(),
-                message: Some(serde_json::to_value(messages.clone())?),
-            };
-            let response_data = serde_json::to_vec(&response)?;
-            Self::write_message(&mut socket, &response_data).await?;
-            messages.iter_mut().for_each(|message| {
-                if message.client_uuid == client_uuid {
-                    message.status = "delivered".to_string();
-                    message.delivered_at = Some(Utc::now());
-                }
-            });
-            self.update_last_seen(client_uuid.clone()).await?;
-            self.mark_messages_delivered(messages.iter().map(|message| message.id).collect()).await?;
-        } else if request.action == "register" {
-            info!("Registering new client: {}", client_uuid);
-            self.register_client(client_uuid.clone()).await?;
-            let response = ServerResponse {
-                status: "ok".to_string(),
-                message: Some(serde_json::to_value(format!("Registered successfully: {}", client_uuid))?),
-            };
-            let response_data = serde_json::to_vec(&response)?;
-            Self::write_message(&mut socket, &response_data).await?;
-            info!("Client {} registered successfully", client_uuid);
+        let rate_key = if matches!(frame.request, Request::Hello | Request::Register(_)) {
+            RateKey::Address(ip)
         } else {
-            error!("Unknown action received: {}", request.action);
-            let response = ServerResponse {
-                status: "error".to_string(),
-                message: Some(serde_json::to_value(format!("Unknown action: {}", request.action))?),
-            };
-            let response_data = serde_json::to_vec(&response)?;
-            Self::write_message(&mut socket, &response_data).await?;
+            if let Err(e) = self.authenticate(&frame).await.and(self.authorize(&client_uuid).await) {
+                warn!("Rejected {} request from client {}: {}", frame.request.action(), client_uuid, e);
+                // Rejected requests count against the address, the client uuid is not proven.
+                let response = match self.limits.check_rate(RateKey::Address(ip)) {
+                    Ok(()) => self.unauthorized(&client_uuid, e).await?,
+                    Err(rejection) => Response::error(rejection.to_string()),
+                };
+                return self.write_response(&mut socket, &response).await;
+            }
+            RateKey::Client(client_uuid.clone())
+        };
+        if let Err(rejection) = self.limits.check_rate(rate_key) {
+            warn!("Rejected {} request from client {} at {}: {}", frame.request.action(), client_uuid, ip, rejection);
+            return self.write_response(&mut socket, &Response::error(rejection.to_string())).await;
+        }
+        
+        let response = match &frame.request {
+            Request::Subscribe => return self.handle_session(socket, client_uuid).await,
+            Request::Hello => Response::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                server_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            Request::Register(info) => self.handle_register(&frame, info.clone()).await?,
+            Request::Poll => {
+                let messages = self.get_pending_messages(client_uuid.clone()).await?;
+                info!("Polling request from client {}: {} messages found", client_uuid, messages.len());
+                self.lease_messages(messages.iter().map(|message| message.id).collect()).await?;
+                self.update_last_seen(client_uuid.clone()).await?;
+                Response::Messages { messages }
+            }
+            Request::Ack { ids } => {
+                let delivered = self.mark_messages_delivered(client_uuid.clone(), ids.clone()).await?;
+                info!("Client {} acknowledged {} of {} message(s)", client_uuid, delivered, ids.len());
+                self.update_last_seen(client_uuid.clone()).await?;
+                Response::Updated { count: delivered }
+            }
+            Request::Applied { ids } => {
+                let applied = self.mark_messages_applied(client_uuid.clone(), ids.clone()).await?;
+                info!("Client {} applied {} message(s): {:?}", client_uuid, applied, ids);
+                Response::Updated { count: applied }
+            }
+            Request::Failed { ids, reason } => {
+                let failed = self.mark_messages_failed(client_uuid.clone(), ids.clone(), reason.clone()).await?;
+                // The session is not woken, failed messages come back once their retry is due.
+                error!("Client {} failed to apply {} message(s) {:?}: {}", client_uuid, failed, ids, reason);
+                Response::Updated { count: failed }
+            }
+            Request::Ping => Response::Pong,
+        };
+        self.write_response(&mut socket, &response).await
+    }
+
+    async fn handle_register(&self, frame: &ClientFrame, info: ServerInfo) -> Result<Response> {
+        let client_uuid = frame.uuid.clone();
+        info!("Registering client: {} ({:?})", client_uuid, info);
+        let state = self.get_client_state(client_uuid.clone()).await?;
+        let response = if state.is_none() {
+            let secret = auth::generate_secret();
+            let state = if self.require_approval { ClientState::Pending } else { ClientState::Active };
+            self.register_client(client_uuid.clone(), secret.clone(), info, state).await?;
+            info!("Client {} registered successfully ({})", client_uuid, state.as_str());
+            Response::Registered { secret: Some(secret) }
+        } else if state == Some(ClientState::Disabled) {
+            warn!("Client {} is disabled, rejecting registration", client_uuid);
+            Response::Unauthorized {
+                message: format!("Client {} is disabled", client_uuid),
+                register: false,
+            }
+        } else if self.get_client_secret(client_uuid.clone()).await?.is_none() {
+            let secret = auth::generate_secret();
+            self.set_client_secret(client_uuid.clone(), secret.clone()).await?;
+            if self.require_approval {
+                self.set_client_state(client_uuid.clone(), ClientState::Pending).await?;
+            }
+            self.update_client_info(client_uuid.clone(), info).await?;
+            info!("Issued credentials to previously registered client {}", client_uuid);
+            Response::Registered { secret: Some(secret) }
+        } else if let Err(e) = self.authenticate(frame).await {
+            warn!("Client {} is already registered, rejecting registration: {}", client_uuid, e);
+            Response::Unauthorized {
+                message: format!("Client {} is already registered with other credentials", client_uuid),
+                register: false,
+            }
+        } else {
+            self.update_client_info(client_uuid.clone(), info).await?;
+            Response::Registered { secret: None }
+        };
+        Ok(response)
+    }
+
+    /// The reply to a request `authenticate` or `authorize` rejected, telling a client that holds no
+    /// credentials, because they were revoked or it was deleted, to register again.
+    async fn unauthorized(&self, client_uuid: &str, e: anyhow::Error) -> Result<Response> {
+        let register = self.get_client_secret(client_uuid.to_string()).await?.is_none();
+        Ok(Response::Unauthorized { message: e.to_string(), register })
+    }
+
+    /// Checks the request signature against the secret issued to the client at registration.
+    async fn authenticate(&self, frame: &ClientFrame) -> Result<()> {
+        let request_auth = frame.auth.as_ref().ok_or_else(|| anyhow::anyhow!("Request is not signed"))?;
+        let now = Utc::now().timestamp();
+        if !request_auth.is_fresh(now) {
+            return Err(anyhow::anyhow!("Request timestamp is too far from server time"));
+        }
+        let secret = self.get_client_secret(frame.uuid.clone()).await?
+            .ok_or_else(|| anyhow::anyhow!("Client has no credentials, it has to register"))?;
+        if !auth::verify(&secret, &frame.uuid, frame.request.action(), frame.request.ids(), request_auth) {
+            return Err(anyhow::anyhow!("Invalid signature"));
+        }
+        // Nonces are kept in the database, so a request cannot be replayed against another server sharing it.
+        let expired_before = now - auth::MAX_CLOCK_SKEW_SECS;
+        if !self.record_nonce(frame.uuid.clone(), request_auth.nonce.clone(), request_auth.timestamp, expired_before).await? {
+            return Err(anyhow::anyhow!("Replayed request"));
+        }
+        Ok(())
+    }
+
+    /// Checks that a client is allowed to receive messages: registered, enabled and holding a secret.
+    async fn authorize(&self, client_uuid: &str) -> Result<()> {
+        match self.get_client_state(client_uuid.to_string()).await? {
+            None => return Err(anyhow::anyhow!("Client is not registered")),
+            Some(ClientState::Disabled) => return Err(anyhow::anyhow!("Client is disabled")),
+            Some(ClientState::Pending) => return Err(anyhow::anyhow!("Client is awaiting approval")),
+            Some(_) => {}
+        }
+        if self.get_client_secret(client_uuid.to_string()).await?.is_none() {
+            return Err(anyhow::anyhow!("Client credentials were revoked"));
         }
         Ok(())
     }
 
+    #[cfg(test)]
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    pub fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    pub fn retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Wakes the push session of `client_uuid`, if it has one open. A session of a client
+    /// that was disabled, revoked or deleted meanwhile closes instead of pushing.
+    pub fn notify_client(&self, client_uuid: &str) {
+        if let Some(notify) = self.sessions.lock().unwrap().get(client_uuid) {
+            notify.notify_one();
+        }
+    }
+
+    fn notify_all_sessions(&self) {
+        for notify in self.sessions.lock().unwrap().values() {
+            notify.notify_one();
+        }
+    }
+
+    async fn push_pending_messages<W: AsyncWrite + Unpin>(&self, writer: &mut W, client_uuid: &str) -> Result<()> {
+        let messages = self.get_pending_messages(client_uuid.to_string()).await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.lease_messages(messages.iter().map(|message| message.id).collect()).await?;
+        info!("Pushing {} message(s) to client {}", messages.len(), client_uuid);
+        self.write_response(writer, &Response::Push { messages }).await
+    }
+
+    async fn handle_session<S>(&self, socket: S, client_uuid: String) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(socket);
+        let notify = Arc::new(Notify::new());
+        self.sessions.lock().unwrap().insert(client_uuid.clone(), Arc::clone(&notify));
+        info!("Client {} opened a push session", client_uuid);
+
+        let subscribed = Response::Subscribed {
+            idle_timeout_secs: self.session_idle_timeout.as_secs(),
+        };
+        let result = async {
+            self.write_response(&mut writer, &subscribed).await?;
+            self.update_last_seen(client_uuid.clone()).await?;
+            self.push_pending_messages(&mut writer, &client_uuid).await?;
+
+            // Frames are read on their own task so a push never interrupts a partially read frame.
+            let (frames_tx, mut frames_rx) = mpsc::channel::<Vec<u8>>(8);
+            // The idle timeout below bounds how long a frame may take, so the reader itself waits indefinitely.
+            let codec = self.codec.without_read_timeout();
+            let reader_uuid = client_uuid.clone();
+            let reader_task = tokio::spawn(async move {
+                loop {
+                    match codec.read_frame(&mut reader).await {
+                        Ok(frame) => {
+                            if frames_tx.send(frame).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(FrameError::Closed) => break,
+                        Err(e) => {
+                            warn!("Dropping session of client {}: {}", reader_uuid, e);
+                            break;
+                        }
+                    }
+                }
+            });
+            let mut recheck = tokio::time::interval_at(tokio::time::Instant::now() + self.pending_recheck, self.pending_recheck);
+            recheck.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let result: Result<()> = async {
+                loop {
+                    tokio::select! {
+                        frame = tokio::time::timeout(self.session_idle_timeout, frames_rx.recv()) => {
+                            let frame = match frame {
+                                Ok(Some(frame)) => frame,
+                                Ok(None) => return Ok(()),
+                                Err(_) => return Err(anyhow::anyhow!("Session idle for more than {:?}", self.session_idle_timeout)),
+                            };
+                            let frame = match ClientFrame::parse(&frame) {
+                                Ok(frame) => frame,
+                                Err(response) => {
+                                    warn!("Rejected frame in session of client {}: {:?}", client_uuid, response);
+                                    self.write_response(&mut writer, &response).await?;
+                                    continue;
+                                }
+                            };
+                            // The session was authenticated when it opened, frames for another client do not belong in it.
+                            if frame.uuid != client_uuid {
+                                warn!("Rejected frame of client {} in session of client {}", frame.uuid, client_uuid);
+                                self.write_response(&mut writer, &Response::error("Frame uuid does not match the session")).await?;
+                                continue;
+                            }
+                            if frame.request != Request::Ping {
+                                warn!("Unexpected action {} in session of client {}", frame.request.action(), client_uuid);
+                                continue;
+                            }
+                            self.write_response(&mut writer, &Response::Pong).await?;
+                            self.update_last_seen(client_uuid.clone()).await?;
+                            continue;
+                        }
+                        _ = notify.notified() => {}
+                        _ = recheck.tick() => {}
+                    }
+                    if let Err(e) = self.authorize(&client_uuid).await {
+                        warn!("Closing session of client {}: {}", client_uuid, e);
+                        let response = self.unauthorized(&client_uuid, e).await?;
+                        return self.write_response(&mut writer, &response).await;
+                    }
+                    self.push_pending_messages(&mut writer, &client_uuid).await?;
+                }
+            }.await;
+            reader_task.abort();
+            result
+        }.await;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(&client_uuid).is_some_and(|current| Arc::ptr_eq(current, &notify)) {
+            sessions.remove(&client_uuid);
+        }
+        info!("Push session of client {} closed", client_uuid);
+        result
+    }
+
     #[allow(unused)]
     async fn save_message(&mut self, message: Message) -> Result<u64> {
         self.create_message(message).await
@@ -140,9 +498,110 @@ impl TcpServer {
             created_at: Utc::now(),
             delivered_at: None,
             status: "pending".to_string(),
+            outcome: None,
+            failure_reason: None,
         };
         self.save_message(message).await?;
         Ok(())
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{donate_message, TempDb};
+    use crate::storage::{SqliteStorage, DEFAULT_DB_POOL_SIZE};
+    use gmod_tcp_client_core::{ClientConfig, MessageHandler, TcpClient};
+
+    const WAIT: Duration = Duration::from_secs(10);
+
+    struct Forward(mpsc::UnboundedSender<Message>);
+
+    impl MessageHandler for Forward {
+        fn on_messages(&self, messages: Vec<Message>) {
+            for message in messages {
+                let _ = self.0.send(message);
+            }
+        }
+    }
+
+    async fn wait_for_secret(server: &TcpServer, other_than: Option<&str>) -> String {
+        tokio::time::timeout(WAIT, async {
+            loop {
+                let secret = server.get_client_secret("rp-1".to_string()).await.unwrap();
+                if let Some(secret) = secret.filter(|secret| Some(secret.as_str()) != other_than) {
+                    return secret;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await.expect("client did not register")
+    }
+
+    async fn receive(messages: &mut mpsc::UnboundedReceiver<Message>, message_id: u64) {
+        let message = tokio::time::timeout(WAIT, messages.recv()).await.expect("message was not pushed").unwrap();
+        assert_eq!(message.id, message_id);
+    }
+
+    /// A server on a loopback port, listening in the background. Keep the `TempDb` until the test ends.
+    async fn start_server(name: &str) -> (Arc<TcpServer>, TempDb) {
+        let file = TempDb::new(name);
+        let storage: Arc<dyn Storage> = Arc::new(SqliteStorage::open(&file.0, DEFAULT_DB_POOL_SIZE).unwrap());
+        TcpServer::init_database(&storage, false).await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Arc::new(TcpServer::with_listener(storage, listener).await.unwrap());
+        tokio::spawn(Arc::clone(&server).listen());
+        (server, file)
+    }
+
+    async fn exchange(codec: &FrameCodec, socket: &mut tokio::net::TcpStream, frame: &ClientFrame) -> Response {
+        codec.write_frame(socket, &serde_json::to_vec(frame).unwrap()).await.unwrap();
+        let reply = tokio::time::timeout(WAIT, codec.read_frame(socket)).await.expect("no reply").unwrap();
+        serde_json::from_slice(&reply).unwrap()
+    }
+
+    #[tokio::test]
+    async fn session_rejects_frames_of_another_client() {
+        let (server, _file) = start_server("session_uuid").await;
+        server.register_client("rp-1".to_string(), "secret".to_string(), ServerInfo::default(), ClientState::Active).await.unwrap();
+        let codec = FrameCodec::new(DEFAULT_MAX_FRAME_SIZE, None);
+        let mut socket = tokio::net::TcpStream::connect(server.local_addr().unwrap()).await.unwrap();
+
+        let subscribe = ClientFrame::new("rp-1".to_string(), Request::Subscribe, Some("secret"));
+        assert!(matches!(exchange(&codec, &mut socket, &subscribe).await, Response::Subscribed { .. }));
+        let foreign = ClientFrame::new("rp-2".to_string(), Request::Ping, None);
+        assert!(matches!(exchange(&codec, &mut socket, &foreign).await, Response::Error { .. }));
+        // The session stays open for its own client.
+        let ping = ClientFrame::new("rp-1".to_string(), Request::Ping, None);
+        assert!(matches!(exchange(&codec, &mut socket, &ping).await, Response::Pong));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_registers_again_after_revoke() {
+        let (server, _file) = start_server("reregister").await;
+        let config = ClientConfig {
+            servers: vec![server.local_addr().unwrap().to_string()],
+            uuid: Some("rp-1".to_string()),
+            ..Default::default()
+        };
+
+        let data_dir = std::env::temp_dir().join(format!("gmod_tcp_reregister_{}", std::process::id()));
+        let client = Arc::new(TcpClient::new(&config, &data_dir).unwrap());
+        let (messages_tx, mut messages) = mpsc::unbounded_channel();
+        client.listen(Arc::new(Forward(messages_tx))).await.unwrap();
+
+        let secret = wait_for_secret(&server, None).await;
+        let first = server.create_message(donate_message("rp-1", 1)).await.unwrap();
+        receive(&mut messages, first).await;
+
+        // The session is closed with a request to register, the listen loop does and resubscribes.
+        assert!(server.revoke_client_secret("rp-1".to_string()).await.unwrap());
+        server.notify_client("rp-1");
+        wait_for_secret(&server, Some(&secret)).await;
+        let second = server.create_message(donate_message("rp-1", 2)).await.unwrap();
+        receive(&mut messages, second).await;
+        assert!(client.status().registered);
+
+        client.shutdown();
+        let _ = std::fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::pem::PemObject;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+use gmod_tcp_shared::tls::fingerprint;
+
+const SELF_SIGNED_CERT_PATH: &str = "data/tls/cert.pem";
+const SELF_SIGNED_KEY_PATH: &str = "data/tls/key.pem";
+
+/// Builds the TLS acceptor for the client listener from `.env`, or `None` when TLS is disabled.
+pub fn load_acceptor() -> Result<Option<TlsAcceptor>> {
+    dotenvy::dotenv().ok();
+    let enabled = std::env::var("TLS_ENABLED")
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false);
+    if !enabled {
+        warn!("TLS_ENABLED is not set - client traffic is sent in plaintext!");
+        return Ok(None);
+    }
+
+    let (cert_path, key_path) = match (std::env::var("TLS_CERT_PATH"), std::env::var("TLS_KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => (cert_path, key_path),
+        _ => {
+            generate_self_signed(Path::new(SELF_SIGNED_CERT_PATH), Path::new(SELF_SIGNED_KEY_PATH))?;
+            (SELF_SIGNED_CERT_PATH.to_string(), SELF_SIGNED_KEY_PATH.to_string())
+        }
+    };
+
+    let certs = CertificateDer::pem_file_iter(&cert_path)
+        .with_context(|| format!("Failed to read certificate {}", cert_path))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificate {}", cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&key_path)
+        .with_context(|| format!("Failed to read private key {}", key_path))?;
+    let leaf = certs.first().ok_or_else(|| anyhow::anyhow!("No certificate found in {}", cert_path))?;
+    info!("TLS enabled with certificate {}", cert_path);
+    info!("Certificate SHA-256 fingerprint (set it as tls_fingerprint in data/gmod_tcp/config.json on clients): {}", fingerprint(leaf));
+
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(Some(TlsAcceptor::from(Arc::new(config))))
+}
+
+fn generate_self_signed(cert_path: &Path, key_path: &Path) -> Result<()> {
+    match (cert_path.exists(), key_path.exists()) {
+        (true, true) => return Ok(()),
+        (false, false) => {}
+        _ => return Err(anyhow::anyhow!(
+            "Only one of {} and {} exists, remove it or restore the other one instead of generating a new certificate",
+            cert_path.display(), key_path.display()
+        )),
+    }
+    info!("TLS_CERT_PATH/TLS_KEY_PATH not set, generating a self-signed certificate");
+    if let Some(dir) = key_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let certified = rcgen::generate_simple_self_signed(vec!["gmod_tcp_server".to_string()])?;
+    write_private(key_path, certified.signing_key.serialize_pem().as_bytes())
+        .with_context(|| format!("Failed to write private key {}", key_path.display()))?;
+    std::fs::write(cert_path, certified.cert.pem())?;
+    Ok(())
+}
+
+/// Creates a file only its owner can read, so other local users cannot take the key.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("gmod_tcp_tls_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn generates_key_readable_by_owner_only() {
+        let dir = temp_dir("generate");
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        generate_self_signed(&cert_path, &key_path).unwrap();
+        assert!(cert_path.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&key_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        // An existing pair is kept.
+        let key = std::fs::read(&key_path).unwrap();
+        generate_self_signed(&cert_path, &key_path).unwrap();
+        assert_eq!(std::fs::read(&key_path).unwrap(), key);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_replace_half_a_pair() {
+        let dir = temp_dir("half");
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&cert_path, "kept").unwrap();
+        assert!(generate_self_signed(&cert_path, &key_path).is_err());
+        assert_eq!(std::fs::read_to_string(&cert_path).unwrap(), "kept");
+        assert!(!key_path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+-- A data/server.db as written by the first release, before any column was added.
+CREATE TABLE clients (
+    uuid TEXT PRIMARY KEY,
+    server_name TEXT NOT NULL,
+    registered_at TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_uuid TEXT NOT NULL,
+    message_type TEXT NOT NULL,
+    message_data TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    delivered_at TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    FOREIGN KEY (client_uuid) REFERENCES clients(uuid)
+);
+CREATE TABLE donates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER,
+    client_uuid TEXT NOT NULL,
+    account_name TEXT DEFAULT NULL,
+    account_steam_id TEXT NOT NULL,
+    who_name TEXT NOT NULL,
+    who_steam_id TEXT DEFAULT NULL,
+    donate_type TEXT NOT NULL,
+    value TEXT NOT NULL,
+    faction TEXT NOT NULL,
+    date TEXT NOT NULL,
+    time TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (client_uuid) REFERENCES clients(uuid),
+    FOREIGN KEY (message_id) REFERENCES messages(id)
+);
+
+INSERT INTO clients (uuid, server_name, registered_at, last_seen) VALUES
+    ('rp-1', 'rp-1', '2025-11-28T19:00:00+00:00', '2025-11-28T19:10:00+00:00');
+INSERT INTO messages (client_uuid, message_type, message_data, created_at, delivered_at, status) VALUES
+    ('rp-1', 'donate', '{"id":1,"account":{"name":"Player","steam_id":"STEAM_0:1:156722227"}}', '2025-11-28T19:10:26+00:00', NULL, 'pending'),
+    ('rp-1', 'donate', '{"id":2,"account":{"name":"Player","steam_id":"STEAM_0:1:156722227"}}', '2025-11-28T19:11:00+00:00', '2025-11-28T19:12:00+00:00', 'delivered');
+INSERT INTO donates (message_id, client_uuid, account_name, account_steam_id, who_name, who_steam_id, donate_type, value, faction, date, time, created_at) VALUES
+    (1, 'rp-1', 'Player', 'STEAM_0:1:156722227', 'Anerson Darling', NULL, 'weapon', 'yufu_spear', 'all', '2026-07-09T09:00:00+00:00', '2025-11-28T19:10:26+00:00', '2025-11-28T19:10:26+00:00'),
+    (2, 'rp-1', 'Player', 'STEAM_0:1:156722227', 'Anerson Darling', NULL, 'money', '1000', 'all', '2026-07-09T09:00:00+00:00', '2025-11-28T19:11:00+00:00', '2025-11-28T19:11:00+00:00');
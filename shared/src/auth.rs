@@ -0,0 +1,94 @@
+use hmac::{Hmac, Mac};
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How far a request timestamp may drift from the server clock.
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestAuth {
+    pub timestamp: i64,
+    pub nonce: String,
+    pub signature: String,
+}
+
+impl RequestAuth {
+    /// Signs a request with a fresh timestamp and nonce.
+    pub fn new(secret: &str, uuid: &str, action: &str, ids: &[u64]) -> Self {
+        let timestamp = chrono::Utc::now().timestamp();
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let signature = sign(secret, uuid, action, ids, timestamp, &nonce);
+        Self { timestamp, nonce, signature }
+    }
+
+    /// Whether the request was signed within `MAX_CLOCK_SKEW_SECS` of `now`.
+    pub fn is_fresh(&self, now: i64) -> bool {
+        (now - self.timestamp).abs() <= MAX_CLOCK_SKEW_SECS
+    }
+}
+
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+fn signing_payload(uuid: &str, action: &str, ids: &[u64], timestamp: i64, nonce: &str) -> String {
+    let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+    format!("{}\n{}\n{}\n{}\n{}", uuid, action, ids, timestamp, nonce)
+}
+
+pub fn sign(secret: &str, uuid: &str, action: &str, ids: &[u64], timestamp: i64, nonce: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(signing_payload(uuid, action, ids, timestamp, nonce).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn verify(secret: &str, uuid: &str, action: &str, ids: &[u64], auth: &RequestAuth) -> bool {
+    let Ok(signature) = hex::decode(&auth.signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(signing_payload(uuid, action, ids, auth.timestamp, &auth.nonce).as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    #[test]
+    fn signature_round_trips() {
+        let auth = RequestAuth::new(SECRET, "rp-1", "ack", &[1, 2]);
+        assert!(verify(SECRET, "rp-1", "ack", &[1, 2], &auth));
+        assert!(!verify("other", "rp-1", "ack", &[1, 2], &auth));
+    }
+
+    #[test]
+    fn tampering_breaks_the_signature() {
+        let auth = RequestAuth::new(SECRET, "rp-1", "ack", &[1, 2]);
+        assert!(!verify(SECRET, "rp-2", "ack", &[1, 2], &auth));
+        assert!(!verify(SECRET, "rp-1", "failed", &[1, 2], &auth));
+        assert!(!verify(SECRET, "rp-1", "ack", &[1, 3], &auth));
+        assert!(!verify(SECRET, "rp-1", "ack", &[1], &auth));
+
+        let later = RequestAuth { timestamp: auth.timestamp + 1, ..auth.clone() };
+        assert!(!verify(SECRET, "rp-1", "ack", &[1, 2], &later));
+        let other_nonce = RequestAuth { nonce: "0".repeat(32), ..auth.clone() };
+        assert!(!verify(SECRET, "rp-1", "ack", &[1, 2], &other_nonce));
+        let garbage = RequestAuth { signature: "not hex".to_string(), ..auth };
+        assert!(!verify(SECRET, "rp-1", "ack", &[1, 2], &garbage));
+    }
+
+    #[test]
+    fn rejects_skewed_clocks() {
+        let auth = RequestAuth::new(SECRET, "rp-1", "poll", &[]);
+        assert!(auth.is_fresh(auth.timestamp));
+        assert!(auth.is_fresh(auth.timestamp + MAX_CLOCK_SKEW_SECS));
+        assert!(auth.is_fresh(auth.timestamp - MAX_CLOCK_SKEW_SECS));
+        assert!(!auth.is_fresh(auth.timestamp + MAX_CLOCK_SKEW_SECS + 1));
+        assert!(!auth.is_fresh(auth.timestamp - MAX_CLOCK_SKEW_SECS - 1));
+    }
+}
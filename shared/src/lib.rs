@@ -1 +1,5 @@
-pub mod types;
\ No newline at end of file
+pub mod types;
+pub mod auth;
+pub mod framing;
+pub mod protocol;
+pub mod tls;
\ No newline at end of file
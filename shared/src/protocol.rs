@@ -0,0 +1,179 @@
+use serde::{Serialize, Deserialize};
+
+use crate::auth::RequestAuth;
+use crate::types::{Message, ServerInfo};
+
+/// Version spoken by this build. The original `{"action":"pool"}` protocol is version 1.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest client protocol the server still accepts.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Request {
+    Hello,
+    /// Older clients send no server info, every field of it is optional.
+    Register(ServerInfo),
+    Poll,
+    Ack { ids: Vec<u64> },
+    Applied { ids: Vec<u64> },
+    Failed { ids: Vec<u64>, reason: String },
+    Subscribe,
+    Ping,
+}
+
+impl Request {
+    pub fn action(&self) -> &'static str {
+        match self {
+            Request::Hello => "hello",
+            Request::Register(_) => "register",
+            Request::Poll => "poll",
+            Request::Ack { .. } => "ack",
+            Request::Applied { .. } => "applied",
+            Request::Failed { .. } => "failed",
+            Request::Subscribe => "subscribe",
+            Request::Ping => "ping",
+        }
+    }
+
+    /// Message ids the request refers to, covered by the request signature.
+    pub fn ids(&self) -> &[u64] {
+        match self {
+            Request::Ack { ids } | Request::Applied { ids } | Request::Failed { ids, .. } => ids,
+            _ => &[],
+        }
+    }
+}
+
+/// Every frame a client sends: the protocol version, who is asking and the signed request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientFrame {
+    pub version: u32,
+    pub uuid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<RequestAuth>,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+impl ClientFrame {
+    /// Decodes a client frame, or returns the reply for frames this server cannot serve.
+    pub fn parse(data: &[u8]) -> Result<Self, Response> {
+        let value: serde_json::Value = serde_json::from_slice(data)
+            .map_err(|e| Response::error(format!("Malformed request: {}", e)))?;
+        let version = match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) => version as u32,
+            // Version 1 clients send {"action":"pool","uuid":...} without a version field.
+            None => return Err(Response::upgrade_required(1)),
+        };
+        if version < MIN_PROTOCOL_VERSION {
+            return Err(Response::upgrade_required(version));
+        }
+        if version > PROTOCOL_VERSION {
+            return Err(Response::error(format!(
+                "Unsupported protocol version {}, server speaks up to {}", version, PROTOCOL_VERSION
+            )));
+        }
+        serde_json::from_value(value).map_err(|e| Response::error(format!("Malformed request: {}", e)))
+    }
+
+    pub fn new(uuid: String, request: Request, secret: Option<&str>) -> Self {
+        let auth = secret.map(|secret| RequestAuth::new(secret, &uuid, request.action(), request.ids()));
+        Self {
+            version: PROTOCOL_VERSION,
+            uuid,
+            auth,
+            request,
+        }
+    }
+}
+
+/// Server replies. Error variants keep a `message` string so version 1 clients can still print them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Hello { protocol_version: u32, server_version: String },
+    Registered {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        secret: Option<String>,
+    },
+    Messages { messages: Vec<Message> },
+    Updated { count: usize },
+    Subscribed { idle_timeout_secs: u64 },
+    Push { messages: Vec<Message> },
+    Pong,
+    Unauthorized {
+        message: String,
+        /// Set when the client holds no valid credentials and registering again issues new ones,
+        /// e.g. after a manager revoked them.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        register: bool,
+    },
+    UpgradeRequired { message: String, min_version: u32, server_version: u32 },
+    Error { message: String },
+}
+
+impl Response {
+    pub fn error(message: impl Into<String>) -> Self {
+        Response::Error { message: message.into() }
+    }
+
+    pub fn upgrade_required(client_version: u32) -> Self {
+        Response::UpgradeRequired {
+            message: format!(
+                "Upgrade required: client speaks protocol version {}, server requires {} to {}",
+                client_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+            min_version: MIN_PROTOCOL_VERSION,
+            server_version: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Turns the error variants into an `Err` so callers only match on the replies they expect.
+    pub fn into_result(self) -> anyhow::Result<Self> {
+        match self {
+            Response::Unauthorized { message, .. } => Err(anyhow::anyhow!("Unauthorized: {}", message)),
+            Response::UpgradeRequired { message, .. } => Err(anyhow::anyhow!(message)),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            response => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(data: &str) -> Response {
+        ClientFrame::parse(data.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn parses_current_frames() {
+        let frame = ClientFrame::new("rp-1".to_string(), Request::Ack { ids: vec![1, 2] }, Some("secret"));
+        let parsed = ClientFrame::parse(&serde_json::to_vec(&frame).unwrap()).unwrap();
+        assert_eq!(parsed.version, PROTOCOL_VERSION);
+        assert_eq!(parsed.uuid, "rp-1");
+        assert_eq!(parsed.request, Request::Ack { ids: vec![1, 2] });
+        assert!(parsed.auth.is_some());
+    }
+
+    #[test]
+    fn old_clients_have_to_upgrade() {
+        let Response::UpgradeRequired { min_version, server_version, .. } = rejection(r#"{"action":"pool","uuid":"rp-1"}"#) else {
+            panic!("frame without a version was not asked to upgrade");
+        };
+        assert_eq!((min_version, server_version), (MIN_PROTOCOL_VERSION, PROTOCOL_VERSION));
+        let old = format!(r#"{{"version":{},"uuid":"rp-1","action":"poll"}}"#, MIN_PROTOCOL_VERSION - 1);
+        assert!(matches!(rejection(&old), Response::UpgradeRequired { .. }));
+    }
+
+    #[test]
+    fn rejects_newer_and_malformed_frames() {
+        let newer = format!(r#"{{"version":{},"uuid":"rp-1","action":"poll"}}"#, PROTOCOL_VERSION + 1);
+        assert!(matches!(rejection(&newer), Response::Error { .. }));
+        assert!(matches!(rejection("not json"), Response::Error { .. }));
+        let unknown = format!(r#"{{"version":{},"uuid":"rp-1","action":"launch"}}"#, PROTOCOL_VERSION);
+        assert!(matches!(rejection(&unknown), Response::Error { .. }));
+    }
+}
@@ -0,0 +1,25 @@
+use sha2::{Digest, Sha256};
+
+/// SHA-256 fingerprint of a DER certificate, formatted as `AB:CD:...`.
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Parses a fingerprint written with or without `:` separators.
+pub fn parse_fingerprint(fingerprint: &str) -> anyhow::Result<Vec<u8>> {
+    let hex_digits: String = fingerprint.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    let bytes = hex::decode(&hex_digits)
+        .map_err(|e| anyhow::anyhow!("Invalid certificate fingerprint {}: {}", fingerprint, e))?;
+    if bytes.len() != 32 {
+        return Err(anyhow::anyhow!("Invalid certificate fingerprint {}: expected 32 bytes, got {}", fingerprint, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+pub fn fingerprint_matches(der: &[u8], expected: &[u8]) -> bool {
+    Sha256::digest(der)[..] == *expected
+}
@@ -9,7 +9,11 @@ pub struct Message {
     pub message_data: serde_json::Value,
     pub created_at: DateTime<Utc>,
     pub delivered_at: Option<DateTime<Utc>>,
-    pub status: String
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
 }
 
 /*
@@ -46,25 +50,98 @@ pub struct DonateList {
     pub list: Vec<Donate>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ClientRequest {
-    pub action: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientConnection {
     pub uuid: String,
+    pub server_name: String,
+    pub registered_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    #[serde(default)]
+    pub map: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub state: ClientState,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ServerResponse {
-    pub status: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub message: Option<serde_json::Value>,
+/// Whether the server talks to a registered client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientState {
+    #[default]
+    Active,
+    /// Registered while `REQUIRE_CLIENT_APPROVAL` is set, waits for a manager to enable it.
+    Pending,
+    Disabled,
+    /// Removed by a manager. The uuid may register again as a new client.
+    Deleted,
+}
+
+impl ClientState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientState::Active => "active",
+            ClientState::Pending => "pending",
+            ClientState::Disabled => "disabled",
+            ClientState::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(state: &str) -> Option<Self> {
+        match state {
+            "active" => Some(ClientState::Active),
+            "pending" => Some(ClientState::Pending),
+            "disabled" => Some(ClientState::Disabled),
+            "deleted" => Some(ClientState::Deleted),
+            _ => None,
+        }
+    }
+}
+
+/// What happens to the undelivered messages of a client being disabled or deleted,
+/// e.g. `{"pending": "reassign", "to": "other-uuid"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "pending", rename_all = "snake_case")]
+pub enum PendingMessages {
+    /// Leave them queued for when the client is enabled again.
+    Keep,
+    Cancel,
+    /// Move them, with their donates, to another client.
+    Reassign { to: String },
+}
+
+/// How a game server describes itself when it registers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ServerInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+/// Body of `PATCH /api/clients/{uuid}`. Fields left out are not changed, an empty `notes` clears them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UpdateClientRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ClientConnection {
-    pub uuid: String,
-    pub server_name: String,
-    pub registered_at: DateTime<Utc>,
-    pub last_seen: DateTime<Utc>
+pub struct ConnectionStats {
+    pub active_connections: usize,
+    pub max_connections: usize,
+    pub rate_limit_per_minute: u32,
+    pub accepted: u64,
+    pub rejected_banned: u64,
+    pub rejected_rate_limited: u64,
+    pub rejected_at_capacity: u64,
+    pub banned_ips: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]